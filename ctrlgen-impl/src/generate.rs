@@ -1,24 +1,84 @@
 use proc_macro2::TokenStream;
 use quote::{quote as q, quote_spanned};
-use syn::{parse_quote, punctuated::Punctuated, Token, WhereClause};
+use syn::{parse_quote, punctuated::Punctuated, spanned::Spanned, Token, WhereClause};
 
-#[cfg(feature = "std")]
-fn borrow_toowned() -> TokenStream {
-    q! {::std::borrow::ToOwned}
+#[cfg(feature = "alloc")]
+fn borrow_toowned(_ty: &syn::Type) -> syn::Result<TokenStream> {
+    Ok(q! {::ctrlgen::__private::ToOwned})
 }
-#[cfg(all(feature = "alloc", not(feature = "std")))]
-fn borrow_toowned() -> TokenStream {
-    q! {::alloc::borrow::ToOwned}
+#[cfg(not(feature = "alloc"))]
+fn borrow_toowned(ty: &syn::Type) -> syn::Result<TokenStream> {
+    Err(syn::Error::new_spanned(
+        ty,
+        "Cannot use `ctrlgen_to_owned` without either `std` or `alloc` features of ctrlgen",
+    ))
 }
-#[cfg(all(not(feature = "alloc"), not(feature = "std")))]
-fn borrow_toowned() -> TokenStream {
-    panic!("Cannot use borrow::ToOwned without either `std` or `alloc` features of ctrlgen")
+
+#[cfg(feature = "alloc")]
+fn boxed(_span: &syn::Ident) -> syn::Result<TokenStream> {
+    Ok(q! {::ctrlgen::__private::Box})
+}
+#[cfg(not(feature = "alloc"))]
+fn boxed(span: &syn::Ident) -> syn::Result<TokenStream> {
+    Err(syn::Error::new_spanned(
+        span,
+        "Async methods need either the `nightly`, `std` or `alloc` features of ctrlgen",
+    ))
+}
+
+/// Returns true if `attrs` derive serde's `Serialize` or `Deserialize`
+fn derives_serde(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("derive"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            syn::Meta::List(list) => list.nested.iter().any(|derive| match derive {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => path
+                    .segments
+                    .last()
+                    .is_some_and(|s| s.ident == "Serialize" || s.ident == "Deserialize"),
+                _ => false,
+            }),
+            _ => false,
+        })
 }
 
+use crate::Argument;
+use crate::ImplArgument;
+use crate::ImplConversion;
+use crate::Method;
 use crate::Proxy;
+use crate::RefEnum;
 
 use super::InputData;
+
+/// The parts of the return channel of a method, for either `ctrlgen::Returnval` or
+/// `ctrlgen::StreamReturnval`
+struct ReturnChannel {
+    sender: TokenStream,
+    recv_result: TokenStream,
+    create: TokenStream,
+    recv: TokenStream,
+    is_cancelled: TokenStream,
+}
+
 impl InputData {
+    fn returnval_bounds(&self) -> Vec<syn::WherePredicate> {
+        let mut bounds = vec![];
+        if let Some(returnval_trait) = self.params.returnval.as_ref() {
+            bounds.push(parse_quote! {
+                #returnval_trait : ::ctrlgen::Returnval
+            });
+            if self.has_streaming_methods() {
+                bounds.push(parse_quote! {
+                    #returnval_trait : ::ctrlgen::StreamReturnval
+                });
+            }
+        }
+        bounds
+    }
+
     pub fn make_where_clause(&self) -> WhereClause {
         let mut where_clause = self
             .generics
@@ -28,65 +88,212 @@ impl InputData {
                 where_token: <Token![where]>::default(),
                 predicates: Punctuated::new(),
             });
-        if let Some(returnval_trait) = self.params.returnval.as_ref() {
-            where_clause.predicates.push(parse_quote! {
-                #returnval_trait : ::ctrlgen::Returnval
-            })
-        }
+        where_clause.predicates.extend(self.returnval_bounds());
         where_clause
     }
 
-    pub fn generate_enum(&self) -> TokenStream {
-        let returnval_handler = self.params.returnval.as_ref();
-        let custom_attrs = &self.params.enum_attr[..];
-        let visibility = &self.params.visibility;
+    /// Like [InputData::make_where_clause], but empty if there are no predicates
+    fn maybe_where(&self) -> TokenStream {
+        let where_clause = self.make_where_clause();
+        if where_clause.predicates.is_empty() {
+            TokenStream::new()
+        } else {
+            q! { #where_clause }
+        }
+    }
+
+    /// The message enum, with the generic parameters of the impl
+    fn enum_type(&self) -> TokenStream {
         let enum_name = &self.params.enum_name;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        q! { #enum_name #ty_generics }
+    }
+
+    /// A hidden, uninhabited variant that uses all generic parameters, so the enum can have
+    /// parameters that no message mentions. `attrs` are the attributes of the enum, so the variant
+    /// can be skipped by derives.
+    fn phantom_variant(generics: &syn::Generics, attrs: &[syn::Attribute]) -> TokenStream {
+        if generics.params.is_empty() {
+            return TokenStream::new();
+        }
+        let lifetimes = generics.lifetimes().map(|l| &l.lifetime);
+        let types = generics.type_params().map(|t| &t.ident);
+        let serde_skip = derives_serde(attrs).then(|| q! { #[serde(skip)] });
+        q! {
+            #[doc(hidden)]
+            #serde_skip
+            __CtrlgenPhantom(
+                ::core::marker::PhantomData<(#(&#lifetimes (),)* #(fn() -> #types,)*)>,
+                ::core::convert::Infallible,
+            ),
+        }
+    }
+
+    /// The match arm for [InputData::phantom_variant]
+    fn phantom_case(generics: &syn::Generics) -> TokenStream {
+        if generics.params.is_empty() {
+            return TokenStream::new();
+        }
+        q! {
+            Self::__CtrlgenPhantom(_, never) => match never {},
+        }
+    }
+
+    /// The generics of the borrowed message enum: the generics of the impl, with the lifetime of
+    /// the borrowed arguments added in front
+    fn ref_generics(&self) -> syn::Generics {
+        let mut generics = self.generics.clone();
+        generics.params.insert(0, parse_quote! { '__ctrlgen_msg });
+        generics
+    }
+
+    /// The borrowed message enum, with the generic parameters of [InputData::ref_generics]
+    fn ref_enum_type(&self, ref_enum: &RefEnum) -> TokenStream {
+        let enum_name = &ref_enum.name;
+        let generics = self.ref_generics();
+        let (_, ty_generics, _) = generics.split_for_impl();
+        q! { #enum_name #ty_generics }
+    }
+
+    /// The generics of the impl, with `param` added after the lifetimes
+    fn generics_with(&self, param: syn::GenericParam) -> syn::Generics {
+        let mut generics = self.generics.clone();
+        let idx = generics.lifetimes().count();
+        generics.params.insert(idx, param);
+        generics
+    }
+
+    fn return_channel(
+        &self,
+        method: &Method,
+        returnval_trait: &syn::Type,
+        ret: &syn::Type,
+    ) -> ReturnChannel {
+        let ret = self.return_channel_type(ret);
+        if method.stream {
+            let returnval = q! { <#returnval_trait as ::ctrlgen::StreamReturnval> };
+            ReturnChannel {
+                sender: q! { #returnval::StreamSender<#ret> },
+                recv_result: q! { #returnval::StreamRecvResult<#ret> },
+                create: q! { #returnval::create_stream },
+                recv: q! { #returnval::recv_stream },
+                is_cancelled: q! { #returnval::is_stream_cancelled },
+            }
+        } else {
+            let returnval = q! { <#returnval_trait as ::ctrlgen::Returnval> };
+            ReturnChannel {
+                sender: q! { #returnval::Sender<#ret> },
+                recv_result: q! { #returnval::RecvResult<#ret> },
+                create: q! { #returnval::create },
+                recv: q! { #returnval::recv },
+                is_cancelled: q! { #returnval::is_cancelled },
+            }
+        }
+    }
+
+    /// The type sent over the return channel for a method returning `ret`
+    fn return_channel_type(&self, ret: &syn::Type) -> TokenStream {
+        if self.params.deadline {
+            q! { ::core::result::Result<#ret, ::ctrlgen::deadline::DeadlineExceeded> }
+        } else {
+            q! { #ret }
+        }
+    }
+
+    /// The fields of a message sent by a proxy method, except for the return channel. With
+    /// `borrowed`, for the borrowed message enum: reference arguments are not converted to owned.
+    fn message_fields(&self, method: &Method, borrowed: bool) -> syn::Result<TokenStream> {
+        let mut fields = TokenStream::new();
+        for arg in method.message_args() {
+            let arg_name = &arg.name;
+            if let Some(impl_trait) = &arg.impl_trait {
+                let value = convert_impl_argument(arg_name, impl_trait)?;
+                fields.extend(q! {
+                    #arg_name: #value,
+                });
+            } else if arg.to_owned && !borrowed {
+                let toowned = borrow_toowned(&arg.ty)?;
+                fields.extend(q! {
+                    #arg_name: #toowned::to_owned(#arg_name),
+                });
+            } else {
+                fields.extend(q! {
+                    #arg_name,
+                });
+            }
+        }
+        fields.extend(self.extra_message_fields());
+        Ok(fields)
+    }
+
+    /// Fields that are part of every message, besides the arguments and return channel
+    fn extra_message_fields(&self) -> TokenStream {
+        if self.params.deadline {
+            q! { deadline: ::core::option::Option::None, }
+        } else {
+            TokenStream::new()
+        }
+    }
+
+    /// The variants of the message enum. With `borrowed`, for the borrowed message enum: reference
+    /// arguments borrow for `'__ctrlgen_msg`, and only doc attributes are kept.
+    fn enum_variants(&self, borrowed: bool) -> syn::Result<TokenStream> {
+        let returnval_handler = self.params.returnval.as_ref();
         let mut variants = TokenStream::new();
         for method in &self.methods {
             let variant_name = method.variant_name();
             let mut variant_params = TokenStream::new();
             let doc_attr = &method.doc_attr;
-            for arg in &method.args {
+            for arg in method.message_args() {
                 let argument_name = &arg.name;
-                let argument_type = if !arg.to_owned {
-                    let ty = &arg.ty;
+                let argument_type = if let Some(impl_trait) = &arg.impl_trait {
+                    impl_message_type(impl_trait)?
+                } else if borrowed {
+                    let ty = with_lifetime(&arg.ty, &message_lifetime());
                     q! {#ty}
                 } else {
-                    match &arg.ty {
-                        syn::Type::Reference(r) => {
-                            let ty = &*r.elem;
-                            let toowned = borrow_toowned();
-                            q! {<#ty as #toowned>::Owned}
-                        }
-                        _ => panic!(
-                            "Argument marked with `#[ctrlgen_to_owned]` must be a &reference"
-                        ),
-                    }
+                    owned_argument_type(arg)?
                 };
                 let mut custom_attributes = TokenStream::new();
-                for aa in &arg.enum_attr {
-                    custom_attributes.extend(q! {# #aa});
+                if !borrowed {
+                    for aa in &arg.enum_attr {
+                        custom_attributes.extend(q! {# #aa});
+                    }
                 }
                 variant_params.extend(q! {
                     #custom_attributes #argument_name : #argument_type,
                 });
             }
             if let Some(return_type) = &method.ret {
-                let mut custom_attributes = TokenStream::new();
-                for aa in &method.return_attr {
-                    custom_attributes.extend(q! {# #aa});
-                }
+                let custom_attributes = if borrowed {
+                    &[][..]
+                } else {
+                    &method.return_attr[..]
+                };
                 if let Some(returnval_trait) = returnval_handler {
+                    let sender = self
+                        .return_channel(method, returnval_trait, return_type)
+                        .sender;
                     variant_params.extend(q! {
-                        #custom_attributes ret : <#returnval_trait as ::ctrlgen::Returnval>::Sender<#return_type>,
+                        #(#custom_attributes)* ret : #sender,
                     });
                 }
-            } else {
-                if !method.return_attr.is_empty() {
-                    panic!("`ctrlgen_return_attr[]` used in method without a return type. Add `-> ()` to force using the return channel.");
-                }
+            } else if let Some(attr) = method.return_attr.first() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "`ctrlgen_return_attr[]` used in method without a return type. Add `-> ()` to force using the return channel.",
+                ));
             }
-            let custom_attributes = &method.enum_attr;
+            if self.params.deadline {
+                variant_params.extend(q! {
+                    deadline: ::core::option::Option<::std::time::Instant>,
+                });
+            }
+            let custom_attributes = if borrowed {
+                &[][..]
+            } else {
+                &method.enum_attr[..]
+            };
 
             variants.extend(q! {
                 #(#doc_attr)*
@@ -94,27 +301,125 @@ impl InputData {
                 #variant_name { #variant_params },
             });
         }
-        let maybe_where = if let Some(returnval_trait) = returnval_handler {
-            q! {
-                where #returnval_trait : ::ctrlgen::Returnval
+        Ok(variants)
+    }
+
+    pub fn generate_enum(&self) -> syn::Result<TokenStream> {
+        let custom_attrs = &self.params.enum_attr[..];
+        let visibility = &self.params.visibility;
+        let enum_name = &self.params.enum_name;
+        let variants = self.enum_variants(false)?;
+        let maybe_where = self.maybe_where();
+        let (impl_generics, _, _) = self.generics.split_for_impl();
+        let phantom_variant = Self::phantom_variant(&self.generics, custom_attrs);
+        let mut res = q! {
+            #(#custom_attrs)*
+            #visibility enum #enum_name #impl_generics
+            #maybe_where
+            {
+                #variants
+                #phantom_variant
             }
-        } else {
-            Default::default()
         };
-        q! {
+        if let Some(ref_enum) = &self.params.ref_enum {
+            res.extend(self.generate_ref_enum(ref_enum)?);
+        }
+        Ok(res)
+    }
+
+    /// The borrowed message enum set with `ref enum`, and its conversion to the message enum
+    fn generate_ref_enum(&self, ref_enum: &RefEnum) -> syn::Result<TokenStream> {
+        let custom_attrs = &ref_enum.attrs[..];
+        let visibility = &self.params.visibility;
+        let ref_name = &ref_enum.name;
+        let variants = self.enum_variants(true)?;
+        let maybe_where = self.maybe_where();
+        let generics = self.ref_generics();
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let phantom_variant = Self::phantom_variant(&generics, custom_attrs);
+        let phantom_case = Self::phantom_case(&generics);
+        let enum_type = self.enum_type();
+        let enum_name = &self.params.enum_name;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        let enum_path = ty_generics.as_turbofish();
+        let ref_enum_type = self.ref_enum_type(ref_enum);
+
+        let mut cases = TokenStream::new();
+        for method in &self.methods {
+            let variant_name = method.variant_name();
+            let mut args = TokenStream::new();
+            let mut fields = TokenStream::new();
+            for arg in method.message_args() {
+                let arg_name = &arg.name;
+                args.extend(q! { #arg_name, });
+                if arg.to_owned {
+                    let toowned = borrow_toowned(&arg.ty)?;
+                    fields.extend(q! { #arg_name: #toowned::to_owned(#arg_name), });
+                } else {
+                    fields.extend(q! { #arg_name, });
+                }
+            }
+            if method.ret.is_some() && self.params.returnval.is_some() {
+                args.extend(q! { ret, });
+                fields.extend(q! { ret, });
+            }
+            if self.params.deadline {
+                args.extend(q! { deadline, });
+                fields.extend(q! { deadline, });
+            }
+            cases.extend(q! {
+                Self::#variant_name { #args } => #enum_name #enum_path::#variant_name { #fields },
+            });
+        }
+
+        Ok(q! {
             #(#custom_attrs)*
-            #visibility enum #enum_name
+            #visibility enum #ref_name #impl_generics
             #maybe_where
             {
                 #variants
+                #phantom_variant
+            }
+
+            impl #impl_generics #ref_enum_type
+            #maybe_where
+            {
+                /// Converts the message to the owned message enum, for example to queue it
+                #visibility fn into_owned(self) -> #enum_type {
+                    match self {
+                        #cases
+                        #phantom_case
+                    }
+                }
+            }
+
+            impl #impl_generics ::core::convert::From<#ref_enum_type> for #enum_type
+            #maybe_where
+            {
+                fn from(msg: #ref_enum_type) -> Self {
+                    msg.into_owned()
+                }
             }
+        })
+    }
+
+    pub fn generate_call_impl(&self) -> syn::Result<TokenStream> {
+        let mut res = self.call_impl(&self.generics, self.enum_type(), false)?;
+        if let Some(ref_enum) = &self.params.ref_enum {
+            res.extend(self.call_impl(&self.ref_generics(), self.ref_enum_type(ref_enum), true)?);
         }
+        Ok(res)
     }
 
-    pub fn generate_call_impl(&self) -> TokenStream {
+    /// The `CallMut` or `CallMutAsync` impl for the message enum, or with `borrowed`, for the
+    /// borrowed message enum
+    fn call_impl(
+        &self,
+        generics: &syn::Generics,
+        enum_type: TokenStream,
+        borrowed: bool,
+    ) -> syn::Result<TokenStream> {
         let returnval_handler = self.params.returnval.as_ref();
-        let struct_name = &self.name;
-        let enum_name = &self.params.enum_name;
         let is_async = self.has_async_functions();
 
         let error_type = if let Some(returnval_trait) = returnval_handler {
@@ -125,26 +430,235 @@ impl InputData {
             q! { ::core::convert::Infallible }
         };
 
-        let mut cases = TokenStream::new();
+        let cases = self.dispatch_cases(borrowed, false);
 
         let context_name = q! { __ctrlgen_context };
         let service_name = q! { __ctrlgen_service };
 
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let where_clause = self.make_where_clause();
+        let phantom_case = Self::phantom_case(generics);
+
+        let service_type = &self.service;
+        let context_types: Vec<_> = self
+            .params
+            .context
+            .iter()
+            .map(|(_, ty)| with_context_lifetime(ty))
+            .collect();
+        let context_type = match &context_types[..] {
+            [ty] => q! { #ty },
+            tys => q! { (#(#tys,)*) },
+        };
+        let batch_hook = self.batch_hook.as_ref().map(|hook| {
+            q! {
+                fn batch_hook(#service_name: &mut #service_type) {
+                    #service_name.#hook()
+                }
+            }
+        });
+
+        if !is_async {
+            Ok(q! {
+                impl #impl_generics ::ctrlgen::CallMut < #service_type > for #enum_type
+                #where_clause
+                {
+                    type Error = #error_type;
+                    type Context<'__ctrlgen_ctx> = #context_type;
+                    fn call_mut_with_ctx(self, #service_name: &mut #service_type, #context_name: Self::Context<'_>) -> ::core::result::Result<(), Self::Error> {
+                        match self {
+                            #cases
+                            #phantom_case
+                        }
+                    }
+                    #batch_hook
+                }
+            })
+        } else {
+            let mut where_clause = where_clause;
+            let mut send_assertion = None;
+            if let Some(send) = &self.params.send {
+                let send_bounds: [syn::WherePredicate; 2] = [
+                    syn::parse_quote_spanned! { send.span() => for<'__ctrlgen_send> #service_type: ::core::marker::Send },
+                    syn::parse_quote_spanned! { send.span() => for<'__ctrlgen_send> Self: ::core::marker::Send },
+                ];
+                where_clause.predicates.extend(send_bounds);
+                // The bounds are higher-ranked, as bounds without generic parameters are rejected
+                // as trivial. A non-generic service is asserted to be `Send` instead.
+                if generics.params.is_empty() {
+                    send_assertion = Some(quote_spanned! { send.span() =>
+                        const _: () = {
+                            fn __ctrlgen_assert_send<T: ::core::marker::Send>() {}
+                            let _ = __ctrlgen_assert_send::<#service_type>;
+                        };
+                    });
+                }
+            }
+            let send_bound = self
+                .params
+                .send
+                .as_ref()
+                .map(|send| quote_spanned! { send.span() => + ::core::marker::Send });
+            let output = q! { ::core::result::Result<(), Self::Error> };
+            let call = q! {
+                async move {
+                    match self {
+                        #cases
+                        #phantom_case
+                    }
+                }
+            };
+            // Without `impl Trait` in associated types, the futures are boxed
+            let future_of = |call: TokenStream| -> syn::Result<_> {
+                if cfg!(feature = "nightly") {
+                    return Ok((q! { impl ::core::future::Future<Output = #output> }, call));
+                }
+                let first_async = self.methods.iter().find(|m| m.r#async).map(|m| &m.name);
+                let boxed = boxed(first_async.expect("impl has async methods"))?;
+                Ok((
+                    q! {
+                        ::core::pin::Pin<#boxed<dyn ::core::future::Future<Output = #output> #send_bound + '__ctrlgen__lifetime>>
+                    },
+                    q! { #boxed::pin(#call) },
+                ))
+            };
+            let (future_type, future) = future_of(call)?;
+            let shared_impl = if self.params.concurrent.is_some() {
+                let shared_cases = self.dispatch_cases(borrowed, true);
+                let shared_variants: Vec<_> = self
+                    .methods
+                    .iter()
+                    .filter(|m| m.is_shared())
+                    .map(|m| m.variant_name())
+                    .collect();
+                let is_shared = if shared_variants.is_empty() {
+                    q! { false }
+                } else {
+                    q! { ::core::matches!(self, #(Self::#shared_variants { .. })|*) }
+                };
+                let (shared_future_type, shared_future) = future_of(q! {
+                    async move {
+                        #[allow(unreachable_patterns)]
+                        match self {
+                            #shared_cases
+                            _ => ::core::unreachable!(),
+                        }
+                    }
+                })?;
+                Some(q! {
+                    impl #impl_generics ::ctrlgen::CallSharedAsync < #service_type > for #enum_type
+                    #where_clause
+                    {
+                        type SharedFuture<'__ctrlgen__lifetime> = #shared_future_type
+                            where #service_type: '__ctrlgen__lifetime, Self: '__ctrlgen__lifetime;
+                        fn call_shared_async<'__ctrlgen__lifetime>(self, #service_name: &'__ctrlgen__lifetime #service_type) -> ::core::result::Result<Self::SharedFuture<'__ctrlgen__lifetime>, Self>
+                        where Self: '__ctrlgen__lifetime
+                        {
+                            if #is_shared {
+                                ::core::result::Result::Ok(#shared_future)
+                            } else {
+                                ::core::result::Result::Err(self)
+                            }
+                        }
+                    }
+                })
+            } else {
+                None
+            };
+            let send_impl = self.params.send.is_some().then(|| {
+                q! {
+                    impl #impl_generics ::ctrlgen::CallMutAsyncSend < #service_type > for #enum_type
+                    #where_clause
+                    {
+                        async fn call_mut_async_send_with_ctx<'__ctrlgen__lifetime>(self, #service_name: &'__ctrlgen__lifetime mut #service_type, #context_name: Self::Context<'__ctrlgen__lifetime>) -> #output {
+                            <Self as ::ctrlgen::CallMutAsync<#service_type>>::call_mut_async_with_ctx(self, #service_name, #context_name).await
+                        }
+                    }
+                }
+            });
+            Ok(q! {
+                #send_assertion
+                #send_impl
+                #shared_impl
+                impl #impl_generics ::ctrlgen::CallMutAsync < #service_type > for #enum_type
+                #where_clause
+                {
+                    type Error = #error_type;
+                    type Context<'__ctrlgen_ctx> = #context_type;
+                    type Future<'__ctrlgen__lifetime, '__ctrlgen_ctx> = #future_type
+                        where #service_type: '__ctrlgen__lifetime, Self: '__ctrlgen__lifetime, '__ctrlgen_ctx: '__ctrlgen__lifetime;
+                    fn call_mut_async_with_ctx<'__ctrlgen__lifetime, '__ctrlgen_ctx: '__ctrlgen__lifetime>(self, #service_name: &'__ctrlgen__lifetime mut #service_type, #context_name: Self::Context<'__ctrlgen_ctx>) -> Self::Future<'__ctrlgen__lifetime, '__ctrlgen_ctx>
+                    where Self: '__ctrlgen__lifetime
+                    {
+                        #future
+                    }
+                    #batch_hook
+                }
+            })
+        }
+    }
+
+    /// The match arms calling the service method of each message, or with `shared_only`, of the
+    /// messages dispatched by `CallSharedAsync`
+    fn dispatch_cases(&self, borrowed: bool, shared_only: bool) -> TokenStream {
+        let returnval_handler = self.params.returnval.as_ref();
+        let mut cases = TokenStream::new();
+
+        let service_name = q! { __ctrlgen_service };
+        let is_cancelled_name = q! { __ctrlgen_is_cancelled };
+        let return_value_name = q! { __ctrlgen_return_value };
+
         for method in &self.methods {
+            if shared_only && !method.is_shared() {
+                continue;
+            }
             let method_name = &method.name;
             let variant_name = method.variant_name();
             let mut args = TokenStream::new();
+            let mut call_args = TokenStream::new();
             for arg in &method.args {
                 let arg_name = &arg.name;
+                if let Some(idx) = arg.context {
+                    // Context arguments are matched by name, so a type mismatch points at both the
+                    // context type and the argument type
+                    let context_span = self.params.context[idx].1.span();
+                    let value = if self.params.context.len() == 1 {
+                        quote_spanned! { context_span => __ctrlgen_context }
+                    } else {
+                        let idx = syn::Index {
+                            index: idx as u32,
+                            span: context_span,
+                        };
+                        quote_spanned! { context_span => __ctrlgen_context.#idx }
+                    };
+                    let ty = &arg.ty;
+                    call_args.extend(quote_spanned! { ty.span() =>
+                        {
+                            let #arg_name: #ty = #value;
+                            #arg_name
+                        },
+                    });
+                    continue;
+                }
+                if arg.cancellation_token {
+                    call_args.extend(q! {
+                        ::ctrlgen::CancellationToken::new(&#is_cancelled_name),
+                    });
+                    continue;
+                }
                 args.extend(q! {
                     #arg_name,
-                })
+                });
+                if arg.to_owned && !borrowed {
+                    call_args.extend(q! {
+                        &#arg_name,
+                    });
+                } else {
+                    call_args.extend(q! {
+                        #arg_name,
+                    });
+                }
             }
-            let call_args = if self.params.context.is_some() {
-                q! { #context_name, #args }
-            } else {
-                args.clone()
-            };
 
             let func_call = if method.r#async {
                 q! { #service_name.#method_name(#call_args).await }
@@ -153,12 +667,64 @@ impl InputData {
             };
 
             let mut body = TokenStream::new();
-            if let (Some(_), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+            if self.params.deadline {
+                args.extend(q! { deadline, });
+            }
+            if let (Some(ret_type), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+                let channel = self.return_channel(method, returnval_trait, ret_type);
+                let is_cancelled = &channel.is_cancelled;
+                let send = if method.stream {
+                    q! { <#returnval_trait as ::ctrlgen::StreamReturnval>::send_item(&ret, #return_value_name) }
+                } else {
+                    q! { <#returnval_trait as ::ctrlgen::Returnval>::send(ret, #return_value_name) }
+                };
                 args.extend(q! { ret, });
-                body.extend(q! {
-                    <#returnval_trait as ::ctrlgen::Returnval>::send(ret, #func_call)
+                if self.params.deadline {
+                    body.extend(q! {
+                        if ::ctrlgen::deadline::is_expired(deadline) {
+                            let #return_value_name = ::core::result::Result::Err(::ctrlgen::deadline::DeadlineExceeded);
+                            return #send;
+                        }
+                    });
+                }
+                if self.params.skip_cancelled {
+                    body.extend(q! {
+                        if #is_cancelled(&ret) {
+                            return Ok(());
+                        }
+                    });
+                }
+                if method.has_cancellation_token() {
+                    body.extend(q! {
+                        let #is_cancelled_name = || #is_cancelled(&ret);
+                    });
+                }
+                let wrap_return_value = self.params.deadline.then(|| {
+                    q! { let #return_value_name = ::core::result::Result::Ok(#return_value_name); }
                 });
+                if method.stream {
+                    body.extend(q! {
+                        for #return_value_name in #func_call {
+                            #wrap_return_value
+                            #send?;
+                        }
+                        Ok(())
+                    });
+                } else {
+                    body.extend(q! {
+                        let #return_value_name = #func_call;
+                        #wrap_return_value
+                        #send
+                    });
+                }
             } else {
+                if self.params.deadline {
+                    body.extend(q! {
+                        if ::ctrlgen::deadline::is_expired(deadline) {
+                            return Ok(());
+                        }
+                    });
+                }
                 body.extend(q! {
                     #func_call;
                     Ok(())
@@ -171,71 +737,151 @@ impl InputData {
                 }
             })
         }
+        cases
+    }
 
-        let (impl_generics, _, _) = &self.generics.split_for_impl();
-        let struct_args = &self.struct_args;
-        let where_clause = self.make_where_clause();
+    pub fn generate_coalesce_impl(&self) -> TokenStream {
+        if !self.has_coalescing_methods() {
+            return TokenStream::new();
+        }
+        let enum_type = self.enum_type();
+        let (impl_generics, _, _) = self.generics.split_for_impl();
+        let maybe_where = self.maybe_where();
 
-        let service_type = q! { #struct_name #struct_args };
-        let context_type = if let Some((_, ctx_type)) = &self.params.context {
-            q! { #ctx_type }
-        } else {
-            q! { () }
-        };
+        let mut cases = TokenStream::new();
+        for method in &self.methods {
+            let Some(keys) = &method.coalesce else {
+                continue;
+            };
+            let variant_name = method.variant_name();
+            let pending_keys: Vec<_> = keys
+                .iter()
+                .map(|k| quote::format_ident!("__ctrlgen_pending_{}", k))
+                .collect();
+            let newer_keys: Vec<_> = keys
+                .iter()
+                .map(|k| quote::format_ident!("__ctrlgen_newer_{}", k))
+                .collect();
+            cases.extend(q! {
+                (
+                    Self::#variant_name { #(#keys: #pending_keys,)* .. },
+                    Self::#variant_name { #(#keys: #newer_keys,)* .. },
+                ) => true #(&& #pending_keys == #newer_keys)*,
+            });
+        }
 
-        if !is_async {
-            q! {
-                impl #impl_generics ::ctrlgen::CallMut < #service_type > for #enum_name
-                #where_clause
-                {
-                    type Error = #error_type;
-                    type Context = #context_type;
-                    fn call_mut_with_ctx(self, #service_name: &mut #service_type, #context_name: Self::Context) -> ::core::result::Result<(), Self::Error> {
-                        match self {
-                            #cases
-                        }
+        q! {
+            impl #impl_generics ::ctrlgen::Coalesce for #enum_type
+            #maybe_where
+            {
+                fn coalesces_with(&self, newer: &Self) -> bool {
+                    #[allow(unreachable_patterns)]
+                    match (self, newer) {
+                        #cases
+                        _ => false,
                     }
                 }
             }
-        } else {
-            q! {
-                impl #impl_generics ::ctrlgen::CallMutAsync < #service_type > for #enum_name
-                #where_clause
-                {
-                    type Error = #error_type;
-                    type Context = #context_type;
-                    type Future<'__ctrlgen__lifetime> = impl core::future::Future<Output = ::core::result::Result<(), Self::Error>> + '__ctrlgen__lifetime
-                        where #service_type: '__ctrlgen__lifetime;
-                    fn call_mut_async_with_ctx<'__ctrlgen__lifetime>(self, #service_name: &'__ctrlgen__lifetime mut #service_type, #context_name: Self::Context) -> Self::Future<'__ctrlgen__lifetime> {
-                        async move {
-                            match self {
-                                #cases
-                            }
-                        }
+        }
+    }
+
+    pub fn generate_deadline_impl(&self) -> TokenStream {
+        if !self.params.deadline {
+            return TokenStream::new();
+        }
+        let enum_type = self.enum_type();
+        let (impl_generics, _, _) = self.generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+
+        let variants: Vec<_> = self.methods.iter().map(|m| m.variant_name()).collect();
+        let phantom_case = Self::phantom_case(&self.generics);
+
+        q! {
+            impl #impl_generics ::ctrlgen::deadline::HasDeadline for #enum_type
+            #maybe_where
+            {
+                fn deadline(&self) -> ::core::option::Option<::std::time::Instant> {
+                    match self {
+                        #(Self::#variants { deadline, .. } => *deadline,)*
+                        #phantom_case
                     }
                 }
+
+                fn set_deadline(&mut self, new_deadline: ::core::option::Option<::std::time::Instant>) {
+                    match self {
+                        #(Self::#variants { deadline, .. } => *deadline = new_deadline,)*
+                        #phantom_case
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn generate_priority_impl(&self) -> TokenStream {
+        let enum_type = self.enum_type();
+        let (impl_generics, _, _) = self.generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+
+        // Without any `#[ctrlgen_priority]`, every message has the default priority
+        let body = if self.methods.iter().any(|x| x.priority.is_some()) {
+            let mut cases = TokenStream::new();
+            for method in &self.methods {
+                let variant_name = method.variant_name();
+                let priority = match &method.priority {
+                    Some(p) => q! { #p },
+                    None => q! { Normal },
+                };
+                cases.extend(q! {
+                    Self::#variant_name { .. } => ::ctrlgen::Priority::#priority,
+                });
+            }
+            cases.extend(Self::phantom_case(&self.generics));
+            q! {
+                match self {
+                    #cases
+                }
+            }
+        } else {
+            q! { ::ctrlgen::Priority::Normal }
+        };
+
+        q! {
+            impl #impl_generics ::ctrlgen::Prioritized for #enum_type
+            #maybe_where
+            {
+                fn priority(&self) -> ::ctrlgen::Priority {
+                    #body
+                }
             }
         }
     }
 
-    pub fn generate_proxies(&self) -> TokenStream {
+    pub fn generate_proxies(&self) -> syn::Result<TokenStream> {
         let mut res = TokenStream::new();
         for proxy in self.params.proxies.iter() {
-            res.extend(self.generate_proxy(proxy));
+            res.extend(self.generate_proxy(proxy)?);
         }
-        res
+        Ok(res)
     }
 
-    pub fn generate_proxy(&self, proxy: &Proxy) -> TokenStream {
+    pub fn generate_proxy(&self, proxy: &Proxy) -> syn::Result<TokenStream> {
         match proxy {
             crate::Proxy::Trait(kwd, x) => self.generate_proxy_trait(kwd, x),
+            crate::Proxy::AsyncTrait(kwd, x) => self.generate_async_proxy_trait(kwd, x),
+            crate::Proxy::Router(kwd, x) => self.generate_router_trait(kwd, x),
+            crate::Proxy::RefTrait(kwd, x) => self.generate_ref_proxy_trait(kwd, x),
         }
     }
 
-    pub fn generate_proxy_trait(&self, kwd: &Token![trait], trait_: &syn::Ident) -> TokenStream {
+    pub fn generate_proxy_trait(
+        &self,
+        kwd: &Token![trait],
+        trait_: &syn::Ident,
+    ) -> syn::Result<TokenStream> {
         let returnval_handler = self.params.returnval.as_ref();
         let proxy_name = trait_;
         let enum_name = &self.params.enum_name;
+        let enum_type = self.enum_type();
         let visibility = &self.params.visibility;
 
         let mut methods = TokenStream::new();
@@ -243,28 +889,24 @@ impl InputData {
         for method in &self.methods {
             let method_name = &method.name;
             let variant_name = method.variant_name();
-            let mut args = TokenStream::new();
-            let mut arg_names = TokenStream::new();
+            let args = proxy_arguments(method);
             let doc_attr = &method.doc_attr;
-            for arg in &method.args {
-                let arg_name = &arg.name;
-                let arg_type = &arg.ty;
-                args.extend(q! {
-                    #arg_name: #arg_type,
-                });
-                arg_names.extend(q! {
-                    #arg_name,
-                });
-            }
+            let arg_names = self.message_fields(method, false)?;
             let span = method.name.span();
             if let (Some(ret), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+                let ReturnChannel {
+                    recv_result,
+                    create,
+                    recv,
+                    ..
+                } = self.return_channel(method, returnval_trait, ret);
                 methods.extend(quote_spanned! { span=>
                     #(#doc_attr)*
-                    fn #method_name(&self, #args) -> <#returnval_trait as ::ctrlgen::Returnval>::RecvResult<#ret> {
-                        let ret = <#returnval_trait as ::ctrlgen::Returnval>::create();
+                    fn #method_name(&self, #args) -> #recv_result {
+                        let ret = #create();
                         let msg = #enum_name::#variant_name { #arg_names ret: ret.0 };
-                        <Self as ::ctrlgen::Proxy<#enum_name>>::send(self, msg);
-                        <#returnval_trait as ::ctrlgen::Returnval>::recv(ret.1)
+                        <Self as ::ctrlgen::Proxy<#enum_type>>::send(self, msg);
+                        #recv(ret.1)
                     }
                 })
             } else {
@@ -272,18 +914,384 @@ impl InputData {
                     #(#doc_attr)*
                     fn #method_name(&self, #args) {
                         let msg = #enum_name::#variant_name { #arg_names };
-                        <Self as ::ctrlgen::Proxy<#enum_name>>::send(self, msg);
+                        <Self as ::ctrlgen::Proxy<#enum_type>>::send(self, msg);
                     }
                 })
             }
         }
 
-        q! {
-            #visibility #kwd #proxy_name: ::ctrlgen::Proxy<#enum_name> {
+        let (trait_generics, ty_generics, _) = self.generics.split_for_impl();
+        let generics =
+            self.generics_with(parse_quote! { __CtrlgenProxy: ::ctrlgen::Proxy<#enum_type> });
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+        Ok(q! {
+            #visibility #kwd #proxy_name #trait_generics: ::ctrlgen::Proxy<#enum_type>
+            #maybe_where
+            {
+                #methods
+            }
+
+            impl #impl_generics #proxy_name #ty_generics for __CtrlgenProxy
+            #maybe_where
+            {}
+        })
+    }
+
+    pub fn generate_ref_proxy_trait(
+        &self,
+        kwd: &Token![trait],
+        trait_: &syn::Ident,
+    ) -> syn::Result<TokenStream> {
+        let Some(ref_enum) = &self.params.ref_enum else {
+            return Err(syn::Error::new_spanned(
+                trait_,
+                "`ref trait` proxies send the borrowed message enum. Add a `ref enum` parameter to ctrlgen",
+            ));
+        };
+        let returnval_handler = self.params.returnval.as_ref();
+        let proxy_name = trait_;
+        let ref_name = &ref_enum.name;
+        let ref_enum_type = self.ref_enum_type(ref_enum);
+        let visibility = &self.params.visibility;
+
+        let mut methods = TokenStream::new();
+
+        for method in &self.methods {
+            let method_name = &method.name;
+            let variant_name = method.variant_name();
+            let args = proxy_arguments(method);
+            let doc_attr = &method.doc_attr;
+            let arg_names = self.message_fields(method, true)?;
+            let span = method.name.span();
+            if let (Some(ret), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+                let ReturnChannel {
+                    recv_result,
+                    create,
+                    recv,
+                    ..
+                } = self.return_channel(method, returnval_trait, ret);
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #args) -> #recv_result {
+                        let ret = #create();
+                        let msg = #ref_name::#variant_name { #arg_names ret: ret.0 };
+                        ::ctrlgen::Proxy::send(self, msg);
+                        #recv(ret.1)
+                    }
+                })
+            } else {
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #args) {
+                        let msg = #ref_name::#variant_name { #arg_names };
+                        ::ctrlgen::Proxy::send(self, msg);
+                    }
+                })
+            }
+        }
+
+        let (trait_generics, ty_generics, _) = self.generics.split_for_impl();
+        let generics = self.generics_with(
+            parse_quote! { __CtrlgenProxy: for<'__ctrlgen_msg> ::ctrlgen::Proxy<#ref_enum_type> },
+        );
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+        Ok(q! {
+            #visibility #kwd #proxy_name #trait_generics: for<'__ctrlgen_msg> ::ctrlgen::Proxy<#ref_enum_type>
+            #maybe_where
+            {
+                #methods
+            }
+
+            impl #impl_generics #proxy_name #ty_generics for __CtrlgenProxy
+            #maybe_where
+            {}
+        })
+    }
+
+    pub fn generate_async_proxy_trait(
+        &self,
+        kwd: &Token![trait],
+        trait_: &syn::Ident,
+    ) -> syn::Result<TokenStream> {
+        let returnval_handler = self.params.returnval.as_ref();
+        let proxy_name = trait_;
+        let enum_name = &self.params.enum_name;
+        let enum_type = self.enum_type();
+        let visibility = &self.params.visibility;
+
+        let mut methods = TokenStream::new();
+
+        for method in &self.methods {
+            let method_name = &method.name;
+            let variant_name = method.variant_name();
+            let args = proxy_arguments(method);
+            let doc_attr = &method.doc_attr;
+            let arg_names = self.message_fields(method, false)?;
+            let span = method.name.span();
+            if let (Some(ret), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+                let ReturnChannel {
+                    recv_result,
+                    create,
+                    recv,
+                    ..
+                } = self.return_channel(method, returnval_trait, ret);
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #args) -> impl ::core::future::Future<Output = #recv_result> + '_ {
+                        // Build the message before the future, so it doesn't borrow the arguments
+                        let ret = #create();
+                        let msg = #enum_name::#variant_name { #arg_names ret: ret.0 };
+                        let ret = ret.1;
+                        async move {
+                            <Self as ::ctrlgen::AsyncProxy<#enum_type>>::send_async(self, msg).await;
+                            #recv(ret)
+                        }
+                    }
+                })
+            } else {
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #args) -> impl ::core::future::Future<Output = ()> + '_ {
+                        let msg = #enum_name::#variant_name { #arg_names };
+                        async move {
+                            <Self as ::ctrlgen::AsyncProxy<#enum_type>>::send_async(self, msg).await
+                        }
+                    }
+                })
+            }
+        }
+
+        let (trait_generics, ty_generics, _) = self.generics.split_for_impl();
+        let generics =
+            self.generics_with(parse_quote! { __CtrlgenProxy: ::ctrlgen::AsyncProxy<#enum_type> });
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+        Ok(q! {
+            #visibility #kwd #proxy_name #trait_generics: ::ctrlgen::AsyncProxy<#enum_type>
+            #maybe_where
+            {
+                #methods
+            }
+
+            impl #impl_generics #proxy_name #ty_generics for __CtrlgenProxy
+            #maybe_where
+            {}
+        })
+    }
+
+    pub fn generate_router_trait(
+        &self,
+        kwd: &Token![trait],
+        trait_: &syn::Ident,
+    ) -> syn::Result<TokenStream> {
+        let returnval_handler = self.params.returnval.as_ref();
+        let router_name = trait_;
+        let enum_name = &self.params.enum_name;
+        let enum_type = self.enum_type();
+        let visibility = &self.params.visibility;
+        let key_name = q! { __ctrlgen_key };
+
+        let mut methods = TokenStream::new();
+
+        for method in &self.methods {
+            let method_name = &method.name;
+            let variant_name = method.variant_name();
+            let args = proxy_arguments(method);
+            let doc_attr = &method.doc_attr;
+            let arg_names = self.message_fields(method, false)?;
+            let span = method.name.span();
+            if let (Some(ret), Some(returnval_trait)) = (&method.ret, returnval_handler) {
+                let ReturnChannel {
+                    recv_result,
+                    create,
+                    recv,
+                    ..
+                } = self.return_channel(method, returnval_trait, ret);
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #key_name: __CtrlgenKey, #args) -> ::core::result::Result<#recv_result, Self::Error> {
+                        let ret = #create();
+                        let msg = #enum_name::#variant_name { #arg_names ret: ret.0 };
+                        <Self as ::ctrlgen::RoutingProxy<__CtrlgenKey, #enum_type>>::send_to(self, #key_name, msg)?;
+                        ::core::result::Result::Ok(#recv(ret.1))
+                    }
+                })
+            } else {
+                methods.extend(quote_spanned! { span=>
+                    #(#doc_attr)*
+                    fn #method_name(&self, #key_name: __CtrlgenKey, #args) -> ::core::result::Result<(), Self::Error> {
+                        let msg = #enum_name::#variant_name { #arg_names };
+                        <Self as ::ctrlgen::RoutingProxy<__CtrlgenKey, #enum_type>>::send_to(self, #key_name, msg)
+                    }
+                })
+            }
+        }
+
+        let trait_generics = self.generics_with(parse_quote! { __CtrlgenKey });
+        let (trait_generics, ty_generics, _) = trait_generics.split_for_impl();
+        let mut generics = self.generics_with(parse_quote! { __CtrlgenKey });
+        generics.params.push(
+            parse_quote! { __CtrlgenProxy: ::ctrlgen::RoutingProxy<__CtrlgenKey, #enum_type> },
+        );
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let maybe_where = self.maybe_where();
+        Ok(q! {
+            #visibility #kwd #router_name #trait_generics: ::ctrlgen::RoutingProxy<__CtrlgenKey, #enum_type>
+            #maybe_where
+            {
                 #methods
             }
 
-            impl< T : ::ctrlgen::Proxy<#enum_name>> #proxy_name for T {}
+            impl #impl_generics #router_name #ty_generics for __CtrlgenProxy
+            #maybe_where
+            {}
+        })
+    }
+}
+
+/// The type of an argument in the message enum
+fn owned_argument_type(arg: &Argument) -> syn::Result<TokenStream> {
+    if !arg.to_owned {
+        let ty = &arg.ty;
+        return Ok(q! {#ty});
+    }
+    match &arg.ty {
+        syn::Type::Reference(r) => {
+            let ty = &*r.elem;
+            let toowned = borrow_toowned(&arg.ty)?;
+            Ok(q! {<#ty as #toowned>::Owned})
+        }
+        _ => Err(syn::Error::new_spanned(
+            &arg.ty,
+            "Argument marked with `#[ctrlgen_to_owned]` must be a &reference",
+        )),
+    }
+}
+
+/// The type an `impl Trait` argument is stored as in the message
+fn impl_message_type(impl_trait: &ImplArgument) -> syn::Result<TokenStream> {
+    if let Some(into) = &impl_trait.into {
+        return Ok(q! {#into});
+    }
+    match &impl_trait.conversion {
+        ImplConversion::Into(ty) => Ok(q! {#ty}),
+        ImplConversion::AsRef(ty) => {
+            let toowned = borrow_toowned(ty)?;
+            Ok(q! {<#ty as #toowned>::Owned})
+        }
+        ImplConversion::Other => unreachable!("checked when parsing the argument"),
+    }
+}
+
+/// The arguments of a proxy method sending `method`
+fn proxy_arguments(method: &Method) -> TokenStream {
+    let mut args = TokenStream::new();
+    for arg in method.message_args() {
+        let arg_name = &arg.name;
+        let arg_type = proxy_argument_type(arg);
+        args.extend(q! {
+            #arg_name: #arg_type,
+        });
+    }
+    args
+}
+
+/// The type of an argument in proxy methods. Arguments of `impl Trait`s other than `Into` and
+/// `AsRef` take `impl Into<T>` of the type they are stored as.
+fn proxy_argument_type(arg: &Argument) -> TokenStream {
+    match &arg.impl_trait {
+        Some(ImplArgument {
+            into: Some(into),
+            conversion: ImplConversion::Other,
+        }) => q! { impl ::core::convert::Into<#into> },
+        _ => {
+            let ty = &arg.ty;
+            q! {#ty}
+        }
+    }
+}
+
+/// Converts the `impl Trait` argument `arg_name` to the type it is stored as in the message
+fn convert_impl_argument(
+    arg_name: &syn::Ident,
+    impl_trait: &ImplArgument,
+) -> syn::Result<TokenStream> {
+    let into = impl_trait.into.as_ref();
+    Ok(match (&impl_trait.conversion, into) {
+        (ImplConversion::Into(ty), None) => q! {
+            ::core::convert::Into::<#ty>::into(#arg_name)
+        },
+        (ImplConversion::Into(ty), Some(into)) => q! {
+            <#into as ::core::convert::From<#ty>>::from(::core::convert::Into::<#ty>::into(#arg_name))
+        },
+        (ImplConversion::AsRef(ty), None) => {
+            let toowned = borrow_toowned(ty)?;
+            q! {
+                #toowned::to_owned(::core::convert::AsRef::<#ty>::as_ref(&#arg_name))
+            }
+        }
+        (ImplConversion::AsRef(ty), Some(into)) => q! {
+            <#into as ::core::convert::From<&#ty>>::from(::core::convert::AsRef::<#ty>::as_ref(&#arg_name))
+        },
+        (ImplConversion::Other, Some(into)) => q! {
+            ::core::convert::Into::<#into>::into(#arg_name)
+        },
+        (ImplConversion::Other, None) => unreachable!("checked when parsing the argument"),
+    })
+}
+
+/// The lifetime of the arguments borrowed by the `ref enum`
+fn message_lifetime() -> syn::Lifetime {
+    syn::Lifetime::new("'__ctrlgen_msg", proc_macro2::Span::call_site())
+}
+
+/// Replaces elided lifetimes in a context type, like in `&mut AudioBuffer` or `Clock<'_>`, with
+/// the lifetime of `CallMut::Context<'__ctrlgen_ctx>`
+fn with_context_lifetime(ty: &syn::Type) -> syn::Type {
+    let lifetime = syn::Lifetime::new("'__ctrlgen_ctx", proc_macro2::Span::call_site());
+    with_lifetime(ty, &lifetime)
+}
+
+/// Replaces elided lifetimes in `ty` with `lifetime`
+fn with_lifetime(ty: &syn::Type, lifetime: &syn::Lifetime) -> syn::Type {
+    let mut ty = ty.clone();
+    fill_elided_lifetimes(&mut ty, lifetime);
+    ty
+}
+
+fn fill_elided_lifetimes(ty: &mut syn::Type, lifetime: &syn::Lifetime) {
+    match ty {
+        syn::Type::Reference(r) => {
+            if r.lifetime.is_none() {
+                r.lifetime = Some(lifetime.clone());
+            }
+            fill_elided_lifetimes(&mut r.elem, lifetime);
+        }
+        syn::Type::Path(p) => {
+            for segment in p.path.segments.iter_mut() {
+                if let syn::PathArguments::AngleBracketed(args) = &mut segment.arguments {
+                    for arg in args.args.iter_mut() {
+                        match arg {
+                            syn::GenericArgument::Lifetime(l) if l.ident == "_" => {
+                                *l = lifetime.clone()
+                            }
+                            syn::GenericArgument::Type(t) => fill_elided_lifetimes(t, lifetime),
+                            _ => (),
+                        }
+                    }
+                }
+            }
         }
+        syn::Type::Tuple(t) => t
+            .elems
+            .iter_mut()
+            .for_each(|t| fill_elided_lifetimes(t, lifetime)),
+        syn::Type::Slice(s) => fill_elided_lifetimes(&mut s.elem, lifetime),
+        syn::Type::Array(a) => fill_elided_lifetimes(&mut a.elem, lifetime),
+        syn::Type::Paren(p) => fill_elided_lifetimes(&mut p.elem, lifetime),
+        syn::Type::Group(g) => fill_elided_lifetimes(&mut g.elem, lifetime),
+        _ => (),
     }
 }
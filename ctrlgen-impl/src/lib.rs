@@ -9,6 +9,39 @@ struct Argument {
     ty: syn::Type,
     enum_attr: Vec<proc_macro2::Group>,
     to_owned: bool,
+    /// Set for the argument named like the `ctrlgen::CancellationToken` context value. The argument
+    /// is not part of the message, and is passed a token on dispatch.
+    cancellation_token: bool,
+    /// Set for arguments named like one of the `context(...)` values. The argument is not part of
+    /// the message, and is passed the context value with this index on dispatch.
+    context: Option<usize>,
+    /// Set for `impl Trait` arguments, which are converted by proxies to a concrete type stored in
+    /// the message
+    impl_trait: Option<ImplArgument>,
+}
+
+struct ImplArgument {
+    /// The type stored in the message, set by `#[ctrlgen_into(Type)]`
+    into: Option<syn::Type>,
+    conversion: ImplConversion,
+}
+
+/// How an `impl Trait` argument is converted to the type stored in the message
+enum ImplConversion {
+    /// `impl Into<T>`. Stored as `T` by default.
+    Into(syn::Type),
+    /// `impl AsRef<T>`. Stored as `T::Owned` by default.
+    AsRef(syn::Type),
+    /// Any other `impl Trait`, stored as the type set by `#[ctrlgen_into(Type)]`. Proxies take
+    /// `impl Into<Type>` instead.
+    Other,
+}
+
+impl Argument {
+    /// Returns true if the argument is part of the message
+    fn in_message(&self) -> bool {
+        !self.cancellation_token && self.context.is_none()
+    }
 }
 
 impl std::fmt::Debug for Argument {
@@ -37,6 +70,14 @@ struct Method {
     return_attr: Vec<syn::Attribute>,
     doc_attr: Vec<syn::Attribute>,
     r#async: bool,
+    /// Set by `#[ctrlgen_coalesce(keys...)]`. Contains the key arguments that must be equal for two
+    /// messages to coalesce.
+    coalesce: Option<Vec<Ident>>,
+    /// Set by `#[ctrlgen_priority(low|normal|high)]`
+    priority: Option<Ident>,
+    /// Set when the method returns `ctrlgen::Stream<T>` or `impl Iterator<Item = T>`. `ret` is then
+    /// the item type, sent through a `ctrlgen::StreamReturnval`.
+    stream: bool,
 }
 
 impl Method {
@@ -50,6 +91,21 @@ impl Method {
         ident.set_span(self.name.span());
         ident
     }
+
+    /// The arguments that are part of the message
+    fn message_args(&self) -> impl Iterator<Item = &Argument> {
+        self.args.iter().filter(|arg| arg.in_message())
+    }
+
+    fn has_cancellation_token(&self) -> bool {
+        self.args.iter().any(|arg| arg.cancellation_token)
+    }
+
+    /// Returns true if the method can be dispatched through a shared borrow of the service
+    fn is_shared(&self) -> bool {
+        self.receiver_style == ReceiverStyle::Ref
+            && self.args.iter().all(|arg| arg.context.is_none())
+    }
 }
 
 impl std::fmt::Debug for Method {
@@ -63,11 +119,13 @@ impl std::fmt::Debug for Method {
 }
 
 pub struct InputData {
-    /// Inherent impl name.
-    name: Ident,
+    /// The service type of the inherent impl, as written in the impl. May be a path into another
+    /// module, and include generic arguments.
+    service: syn::Path,
     generics: syn::Generics,
-    struct_args: syn::PathArguments,
     methods: Vec<Method>,
+    /// The method marked `#[ctrlgen_batch_hook]`
+    batch_hook: Option<Ident>,
     params: Params,
 }
 
@@ -75,10 +133,30 @@ impl InputData {
     fn has_async_functions(&self) -> bool {
         self.methods.iter().any(|x| x.r#async)
     }
+
+    fn has_coalescing_methods(&self) -> bool {
+        self.methods.iter().any(|x| x.coalesce.is_some())
+    }
+
+    fn has_streaming_methods(&self) -> bool {
+        self.methods.iter().any(|x| x.stream)
+    }
 }
 
 pub enum Proxy {
     Trait(Token![trait], syn::Ident),
+    /// A proxy trait with async methods, implemented for all `ctrlgen::AsyncProxy`s
+    AsyncTrait(Token![trait], syn::Ident),
+    /// A proxy trait whose methods take an extra routing key, implemented for all `ctrlgen::RoutingProxy`s
+    Router(Token![trait], syn::Ident),
+    /// A proxy trait sending the borrowed message enum, implemented for all proxies of it
+    RefTrait(Token![trait], syn::Ident),
+}
+
+/// The borrowed message enum set with `ref enum Name`
+pub struct RefEnum {
+    attrs: Vec<syn::Attribute>,
+    name: Ident,
 }
 
 pub struct Params {
@@ -87,7 +165,25 @@ pub struct Params {
     proxies: Vec<Proxy>,
     enum_attr: Vec<syn::Attribute>,
     enum_name: Ident,
-    context: Option<(syn::Ident, syn::Type)>,
+    /// The values set with `context(name: Type, ...)`, passed to the methods taking an argument with
+    /// the same name
+    context: Vec<(syn::Ident, syn::Type)>,
+    /// The `context(...)` value of type `ctrlgen::CancellationToken`. It is provided by the dispatch
+    /// instead of the caller, so it is not part of `Context`.
+    cancellation_token: Option<Ident>,
+    /// Skip calling methods whose return value receiver has been dropped. Methods that are already
+    /// running are not aborted.
+    skip_cancelled: bool,
+    /// Add a `deadline` to every message, after which it is rejected instead of handled
+    deadline: bool,
+    /// Give every method without a return type a `()` return channel, as if marked `#[ctrlgen_ack]`
+    ack_all: bool,
+    /// Also generate a message enum that borrows the reference arguments
+    ref_enum: Option<RefEnum>,
+    /// Require the futures of async messages to be `Send`, and implement `CallMutAsyncSend`
+    send: Option<Ident>,
+    /// Implement `CallSharedAsync`, to run the `&self` methods of async messages concurrently
+    concurrent: Option<Ident>,
 }
 
 pub mod generate;
@@ -101,9 +197,12 @@ pub fn ctrlgen_impl(attrs: TokenStream, input: TokenStream) -> syn::Result<Token
     let mut imp: syn::ItemImpl = syn::parse2(input)?;
     let input_data = InputData::parse_inherent_impl(&mut imp, params)?;
 
-    ret.extend(input_data.generate_enum());
-    ret.extend(input_data.generate_call_impl());
-    ret.extend(input_data.generate_proxies());
+    ret.extend(input_data.generate_enum()?);
+    ret.extend(input_data.generate_call_impl()?);
+    ret.extend(input_data.generate_coalesce_impl());
+    ret.extend(input_data.generate_priority_impl());
+    ret.extend(input_data.generate_deadline_impl());
+    ret.extend(input_data.generate_proxies()?);
     ret.extend(quote::quote! {#imp});
 
     syn::Result::<TokenStream>::Ok(ret)
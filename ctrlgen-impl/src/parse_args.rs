@@ -6,6 +6,7 @@ use syn::Token;
 
 use crate::Params;
 use crate::Proxy;
+use crate::RefEnum;
 
 impl Parse for Proxy {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
@@ -16,6 +17,18 @@ impl Parse for Proxy {
         if input.peek(Token![trait]) {
             let kwd: Token![trait] = input.parse()?;
             Ok(Self::Trait(kwd, input.parse()?))
+        } else if input.peek(Token![async]) && input.peek2(Token![trait]) {
+            let _async: Token![async] = input.parse()?;
+            Ok(Self::AsyncTrait(input.parse()?, input.parse()?))
+        } else if input.peek(syn::Ident) && input.peek2(Token![trait]) {
+            let kind: syn::Ident = input.parse()?;
+            if kind != "router" {
+                return Err(syn::Error::new_spanned(kind, "Expected `router trait`"));
+            }
+            Ok(Self::Router(input.parse()?, input.parse()?))
+        } else if input.peek(Token![ref]) && input.peek2(Token![trait]) {
+            let _ref: Token![ref] = input.parse()?;
+            Ok(Self::RefTrait(input.parse()?, input.parse()?))
         // } else if input.peek(Token![impl]) {
         //     let _kwd: Token![impl] = input.parse()?;
         //     let generics = input.parse()?;
@@ -24,7 +37,7 @@ impl Parse for Proxy {
         } else {
             Err(syn::Error::new(
                 input.span(),
-                "Expected `struct`, `trait` or `impl`",
+                "Expected `trait`, `async trait`, `router trait` or `ref trait`",
             ))
         }
     }
@@ -46,7 +59,14 @@ impl Parse for Params {
         let enum_name: syn::Ident = input.parse()?;
         let mut returnval = None;
         let mut proxies = Vec::new();
-        let mut context = None;
+        let mut context = Vec::new();
+        let mut cancellation_token = None;
+        let mut skip_cancelled = false;
+        let mut deadline = false;
+        let mut ack_all = None;
+        let mut ref_enum: Option<RefEnum> = None;
+        let mut send = None;
+        let mut concurrent = None;
 
         while input.peek(Token![,]) {
             let _comma: Token![,] = input.parse()?;
@@ -54,8 +74,21 @@ impl Parse for Params {
                 // Allow trailing comma
                 break;
             }
-            if input.peek(Token![trait]) {
-                proxies.extend(input.parse());
+            if input.peek(Token![trait]) || input.peek2(Token![trait]) {
+                proxies.push(input.parse()?);
+                continue;
+            }
+            if input.peek(Token![#]) || input.peek(Token![ref]) {
+                let attrs = Attribute::parse_outer(input)?;
+                let kwd: Token![ref] = input.parse()?;
+                let _: Token![enum] = input.parse()?;
+                if ref_enum.is_some() {
+                    return Err(syn::Error::new_spanned(kwd, "`ref enum` specified twice"));
+                }
+                ref_enum = Some(RefEnum {
+                    attrs,
+                    name: input.parse()?,
+                });
                 continue;
             }
 
@@ -93,7 +126,7 @@ impl Parse for Params {
                     proxies.extend(punct)
                 }
                 "context" => {
-                    if context.is_some() {
+                    if !context.is_empty() || cancellation_token.is_some() {
                         return Err(syn::Error::new_spanned(
                             arg,
                             "Argument `context` specified twice",
@@ -102,12 +135,49 @@ impl Parse for Params {
                     let contents;
                     let _paren = syn::parenthesized!(contents in input);
 
-                    let ident = contents.parse()?;
-                    let _colon: Token![:] = contents.parse()?;
-                    let ty = contents.parse()?;
-
-                    context = Some((ident, ty))
+                    let values =
+                        Punctuated::<(syn::Ident, syn::Type), Token![,]>::parse_terminated_with(
+                            &contents,
+                            |input| {
+                                let ident = input.parse()?;
+                                let _colon: Token![:] = input.parse()?;
+                                let ty = input.parse()?;
+                                Ok((ident, ty))
+                            },
+                        )?;
+                    if values.is_empty() {
+                        return Err(syn::Error::new_spanned(
+                            arg,
+                            "Argument `context` needs at least one `name: Type`",
+                        ));
+                    }
+                    for (ident, ty) in values {
+                        if context.iter().any(|(other, _)| *other == ident)
+                            || cancellation_token.as_ref() == Some(&ident)
+                        {
+                            return Err(syn::Error::new_spanned(
+                                ident,
+                                "Context value specified twice",
+                            ));
+                        }
+                        if is_cancellation_token(&ty) {
+                            if cancellation_token.is_some() {
+                                return Err(syn::Error::new_spanned(
+                                    ty,
+                                    "Only one context value can be a `ctrlgen::CancellationToken`",
+                                ));
+                            }
+                            cancellation_token = Some(ident);
+                        } else {
+                            context.push((ident, ty));
+                        }
+                    }
                 }
+                "skip_cancelled" => skip_cancelled = true,
+                "deadline" => deadline = true,
+                "send" => send = Some(arg),
+                "concurrent" => concurrent = Some(arg),
+                "ack_all" => ack_all = Some(arg),
                 _ => {
                     return Err(syn::Error::new(
                         arg.span(),
@@ -117,6 +187,13 @@ impl Parse for Params {
             };
         }
 
+        if let (Some(ack_all), None) = (&ack_all, &returnval) {
+            return Err(syn::Error::new_spanned(
+                ack_all,
+                "Argument `ack_all` needs a `returnval` parameter",
+            ));
+        }
+
         Ok(Self {
             visibility,
             enum_name,
@@ -124,6 +201,29 @@ impl Parse for Params {
             proxies,
             enum_attr,
             context,
+            cancellation_token,
+            skip_cancelled,
+            deadline,
+            ack_all: ack_all.is_some(),
+            ref_enum,
+            send,
+            concurrent,
         })
     }
 }
+
+/// Returns true for `ctrlgen::CancellationToken`, with or without a lifetime
+fn is_cancellation_token(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) if p.qself.is_none() => {
+            let segments: Vec<_> = p
+                .path
+                .segments
+                .iter()
+                .map(|s| s.ident.to_string())
+                .collect();
+            matches!(&segments[..], [c, t] if c == "ctrlgen" && t == "CancellationToken")
+        }
+        _ => false,
+    }
+}
@@ -1,7 +1,11 @@
+use proc_macro2::Delimiter;
+use proc_macro2::Group;
+use proc_macro2::TokenStream;
 use proc_macro2::TokenTree;
-use syn::{parse_quote, Attribute};
+use syn::punctuated::Punctuated;
+use syn::{parse_quote, parse_quote_spanned, Attribute, Ident, Token};
 
-use crate::{Argument, Method, Params};
+use crate::{Argument, ImplArgument, ImplConversion, Method, Params};
 
 use super::{InputData, ReceiverStyle};
 impl InputData {
@@ -24,7 +28,7 @@ impl InputData {
             ));
         }
         let generics = item.generics.clone();
-        let (name, struct_args) = match &*item.self_ty {
+        let service = match &*item.self_ty {
             syn::Type::Path(p) => {
                 if p.qself.is_some() {
                     return Err(syn::Error::new_spanned(
@@ -32,57 +36,169 @@ impl InputData {
                         "Impl has some tricky type. This is not supported",
                     ));
                 }
-                if p.path.segments.len() != 1 {
-                    return Err(syn::Error::new_spanned(
-                        p,
-                        "Impl type must be a single ident with optional arguments",
-                    ));
-                }
-                let segment = p.path.segments[0].clone();
-                (segment.ident, segment.arguments)
+                p.path.clone()
             }
             _ => return Err(syn::Error::new_spanned(
                 &*item.self_ty,
-                "Type for `impl` should be a simple identifier without any paths or other tricks.",
+                "Type for `impl` should be a path to the service type, without any other tricks.",
             )),
         };
 
         let mut methods = Vec::with_capacity(item.items.len());
+        let mut batch_hook = None;
 
         for item in &mut item.items {
             if let syn::ImplItem::Method(method) = item {
-                if method.defaultness.is_some() {
-                    panic!("`default` not supported");
+                if let Some(x) = method.defaultness {
+                    return Err(syn::Error::new_spanned(x, "`default` not supported"));
+                }
+
+                if let Some(idx) = method
+                    .attrs
+                    .iter()
+                    .position(|a| a.path.is_ident("ctrlgen_batch_hook"))
+                {
+                    let attr = method.attrs.remove(idx);
+                    if batch_hook.is_some() {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "Only one method can be marked `ctrlgen_batch_hook`",
+                        ));
+                    }
+                    batch_hook = Some(parse_batch_hook(&method.sig)?);
+                    continue;
                 }
 
                 methods.push(parse_method(
                     &mut method.sig,
                     &mut method.attrs,
                     returnval_mode,
+                    params.deadline,
+                    params.ack_all,
                     &params.context,
+                    params.cancellation_token.as_ref(),
                 )?);
             }
         }
 
+        if let Some(concurrent) = &params.concurrent {
+            if !methods.iter().any(|m| m.r#async) {
+                return Err(syn::Error::new_spanned(
+                    concurrent,
+                    "`concurrent` runs async methods concurrently, but this impl has no async methods",
+                ));
+            }
+        }
+
+        // The generated items are outside of the impl block, where `Self` means something else
+        let self_ty = quote::quote! { #service };
+        for method in &mut methods {
+            method.replace_self(&self_ty)?;
+        }
+
         Ok(InputData {
-            name,
+            service,
             generics,
-            struct_args,
             methods,
+            batch_hook,
             params,
         })
     }
 }
 
+impl Method {
+    /// Replaces `Self` in the argument and return types with the service type `self_ty`
+    fn replace_self(&mut self, self_ty: &TokenStream) -> syn::Result<()> {
+        for arg in &mut self.args {
+            replace_self(&mut arg.ty, self_ty)?;
+            if let Some(impl_trait) = &mut arg.impl_trait {
+                if let Some(into) = &mut impl_trait.into {
+                    replace_self(into, self_ty)?;
+                }
+                match &mut impl_trait.conversion {
+                    ImplConversion::Into(ty) | ImplConversion::AsRef(ty) => {
+                        replace_self(ty, self_ty)?
+                    }
+                    ImplConversion::Other => (),
+                }
+            }
+        }
+        if let Some(ret) = &mut self.ret {
+            replace_self(ret, self_ty)?;
+        }
+        Ok(())
+    }
+}
+
+fn replace_self(ty: &mut syn::Type, self_ty: &TokenStream) -> syn::Result<()> {
+    *ty = syn::parse2(replace_self_tokens(quote::quote! { #ty }, self_ty))?;
+    Ok(())
+}
+
+/// Replaces `Self` with `self_ty`, and `Self::Assoc` with `<self_ty>::Assoc`
+fn replace_self_tokens(tokens: TokenStream, self_ty: &TokenStream) -> TokenStream {
+    let mut res = TokenStream::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(ident) if ident == "Self" => {
+                if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ':') {
+                    res.extend(quote::quote! { <#self_ty> });
+                } else {
+                    res.extend(self_ty.clone());
+                }
+            }
+            TokenTree::Group(group) => {
+                let mut replaced = Group::new(
+                    group.delimiter(),
+                    replace_self_tokens(group.stream(), self_ty),
+                );
+                replaced.set_span(group.span());
+                res.extend([TokenTree::Group(replaced)]);
+            }
+            token => res.extend([token]),
+        }
+    }
+    res
+}
+
+fn parse_batch_hook(method_signature: &syn::Signature) -> syn::Result<Ident> {
+    let takes_only_mut_self = matches!(
+        method_signature.inputs.iter().collect::<Vec<_>>()[..],
+        [syn::FnArg::Receiver(syn::Receiver {
+            reference: Some((_, None)),
+            mutability: Some(_),
+            ..
+        })]
+    );
+    if !takes_only_mut_self
+        || method_signature.asyncness.is_some()
+        || !method_signature.generics.params.is_empty()
+        || !matches!(method_signature.output, syn::ReturnType::Default)
+    {
+        return Err(syn::Error::new_spanned(
+            method_signature,
+            "`ctrlgen_batch_hook` method must be a synchronous `fn(&mut self)` without a return type",
+        ));
+    }
+    Ok(method_signature.ident.clone())
+}
+
 fn parse_method(
     method_signature: &mut syn::Signature,
     attrs: &mut Vec<syn::Attribute>,
     returnval_mode: bool,
-    context: &Option<(syn::Ident, syn::Type)>,
+    deadline_mode: bool,
+    ack_all: bool,
+    context: &[(syn::Ident, syn::Type)],
+    cancellation_token: Option<&syn::Ident>,
 ) -> syn::Result<Method> {
     let mut enum_attr = vec![];
     let mut return_attr = vec![];
     let mut doc_attr = vec![];
+    let mut coalesce = None;
+    let mut priority = None;
+    let mut ack = ack_all;
     let r#async = method_signature.asyncness.is_some();
     if let Some(x) = method_signature.constness {
         return Err(syn::Error::new_spanned(x, "ctrlgen does not support const"));
@@ -121,20 +237,84 @@ fn parse_method(
         match a.path.get_ident() {
             Some(x) if x == "ctrlgen_enum_attr" || x == "ctrlgen_return_attr" => {
                 let g = match a.tokens.clone().into_iter().next() {
-                    Some(TokenTree::Group(g)) => g,
+                    Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket => g,
                     _ => {
                         return Err(syn::Error::new_spanned(
                             a,
-                            "Input of `ctrlgen_{{enum|return}}_attr` should be single [...] group",
+                            "Input of `ctrlgen_{enum|return}_attr` should be single [...] group",
                         ));
                     }
                 };
-                let attr: Attribute = parse_quote! { # #g };
-                match x {
-                    x if x == "ctrlgen_enum_attr" => enum_attr.push(attr),
-                    x if x == "ctrlgen_return_attr" => return_attr.push(attr),
-                    _ => unreachable!(),
+                let attr: Attribute = parse_quote_spanned! { a.pound_token.span=> # #g };
+                if x == "ctrlgen_enum_attr" {
+                    enum_attr.push(attr)
+                } else {
+                    return_attr.push(attr)
+                }
+            }
+            Some(x) if x == "ctrlgen_to_owned" => {
+                return Err(syn::Error::new_spanned(
+                    a,
+                    "`ctrlgen_to_owned` applies to a single argument. Put it on the argument instead of the method",
+                ));
+            }
+            Some(x) if x == "ctrlgen_coalesce" => {
+                if coalesce.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        a,
+                        "`ctrlgen_coalesce` specified twice",
+                    ));
                 }
+                let keys: Vec<Ident> = if a.tokens.is_empty() {
+                    vec![]
+                } else {
+                    a.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?
+                        .into_iter()
+                        .collect()
+                };
+                coalesce = Some(keys);
+            }
+            Some(x) if x == "ctrlgen_priority" => {
+                if priority.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        a,
+                        "`ctrlgen_priority` specified twice",
+                    ));
+                }
+                let lane: Ident = a.parse_args()?;
+                let variant = match lane.to_string().as_str() {
+                    "low" => "Low",
+                    "normal" => "Normal",
+                    "high" => "High",
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            lane,
+                            "Expected one of `low`, `normal` or `high`",
+                        ))
+                    }
+                };
+                priority = Some(Ident::new(variant, lane.span()));
+            }
+            Some(x) if x == "ctrlgen_ack" => {
+                if !a.tokens.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        a,
+                        "`ctrlgen_ack` does not accept any additional arguments",
+                    ));
+                }
+                if !matches!(method_signature.output, syn::ReturnType::Default) {
+                    return Err(syn::Error::new_spanned(
+                        a,
+                        "`ctrlgen_ack` can only be used on methods without a return type",
+                    ));
+                }
+                if !returnval_mode {
+                    return Err(syn::Error::new_spanned(
+                        a,
+                        "Specify `returnval` parameter to ctrlgen macro to use `ctrlgen_ack`.",
+                    ));
+                }
+                ack = true;
             }
             Some(x) if x == "doc" => {
                 doc_attr.push(a.clone());
@@ -142,14 +322,24 @@ fn parse_method(
             _ => (),
         }
     }
-    attrs.retain(|a| !matches!(a.path.get_ident(), Some(x) if x == "ctrlgen_enum_attr" || x == "ctrlgen_return_attr"));
+    attrs.retain(|a| !matches!(a.path.get_ident(), Some(x) if x == "ctrlgen_enum_attr" || x == "ctrlgen_return_attr" || x == "ctrlgen_coalesce" || x == "ctrlgen_priority" || x == "ctrlgen_ack"));
     let mut args = Vec::with_capacity(method_signature.inputs.len());
     let mut receiver_style = None;
-    let ret = match &method_signature.output {
-        syn::ReturnType::Default => None,
-        syn::ReturnType::Type(_, t) => Some(*t.clone()),
+    let (ret, stream) = match &method_signature.output {
+        // Acknowledged unit methods behave like methods returning `()`
+        syn::ReturnType::Default if ack && returnval_mode => (Some(parse_quote! { () }), false),
+        syn::ReturnType::Default => (None, false),
+        syn::ReturnType::Type(_, t) => match stream_item_type(t) {
+            Some(item) => (Some(item), true),
+            None if is_impl_stream(t) => {
+                return Err(syn::Error::new_spanned(
+                    t,
+                    "Async streams are not supported as return types. Return a `ctrlgen::Stream` or `impl Iterator` to stream items, or a boxed stream to return it as a single value",
+                ))
+            }
+            None => (Some(*t.clone()), false),
+        },
     };
-    let mut is_first_arg = true;
     for input_args in &mut method_signature.inputs {
         match input_args {
             syn::FnArg::Receiver(r) => {
@@ -170,27 +360,16 @@ fn parse_method(
                 }
             }
             syn::FnArg::Typed(arg) => {
-                if is_first_arg {
-                    is_first_arg = false;
-                    if let Some((ctx_name, _ctx_ty)) = context {
-                        if let syn::Pat::Ident(pat) = &*arg.pat {
-                            let arg_name = &pat.ident;
-                            if *arg_name != ctx_name.to_string() {
-                                return Err(syn::Error::new_spanned(&arg, format!("Expected first argument to be the context {ctx_name}. Got {arg_name}")));
-                            }
-                        }
-                        // We're good, skip this argument.
-                        continue;
-                    }
-                }
-
                 let mut enum_attr = vec![];
                 let mut to_owned = false;
-                for a in attrs.iter() {
+                let mut into = None;
+                for a in arg.attrs.iter() {
                     match a.path.get_ident() {
                         Some(x) if x == "ctrlgen_enum_attr" => {
                             match a.tokens.clone().into_iter().next() {
-                                Some(TokenTree::Group(g)) => {
+                                Some(TokenTree::Group(g))
+                                    if g.delimiter() == Delimiter::Bracket =>
+                                {
                                     enum_attr.push(g);
                                 }
                                 _ => return Err(syn::Error::new_spanned(
@@ -206,16 +385,85 @@ fn parse_method(
                                     "`ctrlgen_to_owned` does not accept any additional arguments",
                                 ));
                             }
+                            if !matches!(&*arg.ty, syn::Type::Reference(_)) {
+                                return Err(syn::Error::new_spanned(
+                                    &arg.ty,
+                                    "Argument marked with `#[ctrlgen_to_owned]` must be a &reference",
+                                ));
+                            }
                             to_owned = true;
                         }
+                        Some(x) if x == "ctrlgen_into" => {
+                            if into.is_some() {
+                                return Err(syn::Error::new_spanned(
+                                    a,
+                                    "`ctrlgen_into` specified twice",
+                                ));
+                            }
+                            if !matches!(&*arg.ty, syn::Type::ImplTrait(_)) {
+                                return Err(syn::Error::new_spanned(
+                                    a,
+                                    "`ctrlgen_into` can only be used on `impl Trait` arguments",
+                                ));
+                            }
+                            into = Some(a.parse_args::<syn::Type>()?);
+                        }
                         _ => (),
                     }
                 }
                 arg.attrs.retain(|a| match a.path.get_ident() {
                     Some(x) if x == "ctrlgen_enum_attr" => false,
                     Some(x) if x == "ctrlgen_to_owned" => false,
+                    Some(x) if x == "ctrlgen_into" => false,
                     _ => true,
                 });
+                let impl_trait = match &*arg.ty {
+                    syn::Type::ImplTrait(i) => {
+                        let conversion = impl_conversion(i);
+                        if matches!(conversion, ImplConversion::Other) && into.is_none() {
+                            return Err(syn::Error::new_spanned(
+                                &arg.ty,
+                                "Cannot infer the type to store this argument as in the message. Specify it with `#[ctrlgen_into(Type)]`",
+                            ));
+                        }
+                        Some(ImplArgument { into, conversion })
+                    }
+                    _ => None,
+                };
+
+                if let syn::Pat::Ident(pat) = &*arg.pat {
+                    let idx = context
+                        .iter()
+                        .position(|(ctx_name, _)| pat.ident == *ctx_name);
+                    let is_cancellation_token = cancellation_token == Some(&pat.ident);
+                    if idx.is_some() || is_cancellation_token {
+                        if !enum_attr.is_empty()
+                            || to_owned
+                            || impl_trait.as_ref().is_some_and(|i| i.into.is_some())
+                        {
+                            return Err(syn::Error::new_spanned(
+                                &pat.ident,
+                                "Context arguments are not part of the message, and cannot have `ctrlgen_*` attributes",
+                            ));
+                        }
+                        if is_cancellation_token && (ret.is_none() || !returnval_mode) {
+                            return Err(syn::Error::new_spanned(
+                                &pat.ident,
+                                "The cancellation token can only be passed to methods with a return value",
+                            ));
+                        }
+                        args.push(Argument {
+                            name: pat.ident.clone(),
+                            ty: *arg.ty.clone(),
+                            enum_attr,
+                            to_owned,
+                            cancellation_token: is_cancellation_token,
+                            context: idx,
+                            impl_trait: None,
+                        });
+                        continue;
+                    }
+                }
                 match &*arg.pat {
                     syn::Pat::Ident(pi) => {
                         if pi.by_ref.is_some() {
@@ -224,33 +472,137 @@ fn parse_method(
                         if returnval_mode && pi.ident == "ret" {
                             return Err(syn::Error::new_spanned(&pi.ident, format!("In `returnval` mode, method's arguments cannot be named literally `ret`. Rename it away in `{}`.", method_signature.ident)));
                         }
-                        args.push(Argument { name: pi.ident.clone(), ty: *arg.ty.clone(), enum_attr, to_owned });
+                        if deadline_mode && pi.ident == "deadline" {
+                            return Err(syn::Error::new_spanned(&pi.ident, format!("In `deadline` mode, method's arguments cannot be named literally `deadline`. Rename it away in `{}`.", method_signature.ident)));
+                        }
+                        args.push(Argument { name: pi.ident.clone(), ty: *arg.ty.clone(), enum_attr, to_owned, cancellation_token: false, context: None, impl_trait });
                     }
                     _ => return Err(syn::Error::new_spanned(arg, "ctrlgen does not support method arguments that are patterns, not just simple identifiers")),
                 }
             }
         }
     }
-    if receiver_style.is_none() {
+    let Some(receiver_style) = receiver_style else {
         return Err(syn::Error::new_spanned(
             method_signature,
             "ctrlgen does not support methods that do not accept `self`",
         ));
-    }
-    if is_first_arg && context.is_some() {
-        return Err(syn::Error::new_spanned(
-            &method_signature,
-            "Every method needs to take the context arg when specified",
-        ));
+    };
+    for key in coalesce.iter().flatten() {
+        if !args.iter().any(|arg| arg.name == *key && arg.in_message()) {
+            return Err(syn::Error::new_spanned(
+                key,
+                format!(
+                    "`ctrlgen_coalesce` key `{key}` is not an argument of `{}`",
+                    method_signature.ident
+                ),
+            ));
+        }
     }
     Ok(Method {
         args,
         name: method_signature.ident.clone(),
-        receiver_style: receiver_style.unwrap(),
+        receiver_style,
         ret,
         enum_attr,
         return_attr,
         doc_attr,
         r#async,
+        coalesce,
+        priority,
+        stream,
     })
 }
+
+/// Finds how to convert an `impl Into<T>`, `impl AsRef<T>` or other `impl Trait` argument
+fn impl_conversion(ty: &syn::TypeImplTrait) -> ImplConversion {
+    ty.bounds
+        .iter()
+        .find_map(|bound| match bound {
+            syn::TypeParamBound::Trait(t) => {
+                let segment = t.path.segments.last()?;
+                let target = match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+                        syn::GenericArgument::Type(t) => t.clone(),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                if segment.ident == "Into" {
+                    Some(ImplConversion::Into(target))
+                } else if segment.ident == "AsRef" {
+                    Some(ImplConversion::AsRef(target))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .unwrap_or(ImplConversion::Other)
+}
+
+/// Returns true if `ty` is an `impl Stream`, which cannot be stored in the message's return channel
+fn is_impl_stream(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::ImplTrait(i) => i.bounds.iter().any(|bound| match bound {
+            syn::TypeParamBound::Trait(t) => t
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Stream"),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Returns the item type if `ty` is `ctrlgen::Stream<'_, T>`, or `impl Iterator<Item = T>`.
+///
+/// The path has to name the `ctrlgen` crate, so that other types called `Stream` are returned as
+/// plain values.
+fn stream_item_type(ty: &syn::Type) -> Option<syn::Type> {
+    match ty {
+        syn::Type::Path(p) if p.qself.is_none() => {
+            let segments: Vec<_> = p
+                .path
+                .segments
+                .iter()
+                .map(|s| s.ident.to_string())
+                .collect();
+            let is_stream = matches!(&segments[..], [c, s] if c == "ctrlgen" && s == "Stream");
+            if !is_stream {
+                return None;
+            }
+            match &p.path.segments.last()?.arguments {
+                syn::PathArguments::AngleBracketed(args) => {
+                    args.args.iter().find_map(|arg| match arg {
+                        syn::GenericArgument::Type(t) => Some(t.clone()),
+                        _ => None,
+                    })
+                }
+                _ => None,
+            }
+        }
+        syn::Type::ImplTrait(i) => i.bounds.iter().find_map(|bound| match bound {
+            syn::TypeParamBound::Trait(t) => {
+                let segment = t.path.segments.last()?;
+                if segment.ident != "Iterator" {
+                    return None;
+                }
+                match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(args) => {
+                        args.args.iter().find_map(|arg| match arg {
+                            syn::GenericArgument::Binding(b) if b.ident == "Item" => {
+                                Some(b.ty.clone())
+                            }
+                            _ => None,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,37 @@
+//! Run with `cargo test -p ctrlgen-impl --no-default-features`
+#![cfg(not(feature = "alloc"))]
+
+use quote::quote;
+
+fn error(input: proc_macro2::TokenStream) -> String {
+    ctrlgen_impl::ctrlgen_impl(quote! { enum Msg }, input)
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn to_owned_needs_alloc() {
+    let err = error(quote! {
+        impl Service {
+            fn set(&mut self, #[ctrlgen_to_owned] name: &str) {}
+        }
+    });
+    assert_eq!(
+        err,
+        "Cannot use `ctrlgen_to_owned` without either `std` or `alloc` features of ctrlgen"
+    );
+}
+
+#[test]
+#[cfg(not(feature = "nightly"))]
+fn boxed_futures_need_alloc() {
+    let err = error(quote! {
+        impl Service {
+            async fn wait(&mut self) {}
+        }
+    });
+    assert_eq!(
+        err,
+        "Async methods need either the `nightly`, `std` or `alloc` features of ctrlgen"
+    );
+}
@@ -0,0 +1,102 @@
+//! Deadlines for messages.
+//!
+//! With the `deadline` parameter, every generated message carries an optional deadline. Messages that
+//! are dispatched after their deadline are rejected without calling the service method, and the
+//! return channel receives `Err(DeadlineExceeded)` instead of the method's return value.
+//!
+//! The deadline is set through the [WithDeadline] proxy adapter:
+//!
+//! ```rust,ignore
+//! #[ctrlgen::ctrlgen(pub enum ServiceMsg, trait ServiceProxy, returnval = TokioRetval, deadline)]
+//! impl Service {
+//!     pub fn increment_by(&mut self, arg: i32) -> i32 { ... }
+//! }
+//!
+//! let promise = proxy.with_timeout(Duration::from_millis(100)).increment_by(2);
+//! match promise.await {
+//!     Some(Ok(value)) => ...,
+//!     Some(Err(DeadlineExceeded)) => ...,
+//!     None => ...,
+//! }
+//! ```
+//!
+//! The deadline is only checked when the message is dispatched:
+//!
+//!  - A message that is never dispatched, for example because the service is stuck, does not
+//!    resolve its return channel at the deadline. Combine the deadline with a timeout on the
+//!    receiving side, like `Promise::with_timeout`, to stop waiting for it.
+//!  - Methods without a return value have no return channel, so their expired messages are dropped
+//!    silently.
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Proxy;
+
+/// Sent over the return channel when a message was dispatched after its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+impl std::error::Error for DeadlineExceeded {}
+impl std::fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Deadline exceeded before the message was handled")
+    }
+}
+
+/// Implemented for message enums generated with the `deadline` parameter
+pub trait HasDeadline {
+    fn deadline(&self) -> Option<Instant>;
+    fn set_deadline(&mut self, deadline: Option<Instant>);
+}
+
+/// Returns true if `deadline` has passed
+pub fn is_expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// A proxy adapter that sets a deadline on every message sent through it.
+///
+/// The deadline only applies once the message is dispatched. Until then, the return value of a
+/// message sent through it keeps waiting, even after the deadline has passed. See [DeadlineExt].
+pub struct WithDeadline<'a, P: ?Sized> {
+    proxy: &'a P,
+    deadline: Instant,
+}
+
+impl<Msg: HasDeadline, P: Proxy<Msg> + ?Sized> Proxy<Msg> for WithDeadline<'_, P> {
+    fn send(&self, mut msg: Msg) {
+        msg.set_deadline(Some(self.deadline));
+        self.proxy.send(msg)
+    }
+}
+
+/// Adds [`with_deadline`](DeadlineExt::with_deadline) and [`with_timeout`](DeadlineExt::with_timeout)
+/// to proxies of messages generated with the `deadline` parameter.
+///
+/// # Undispatched messages
+///
+/// The deadline is checked by the service when it dispatches the message, not by the caller. If the
+/// message is never dispatched, because the service has stopped or its queue is stalled, the return
+/// value does not resolve to `Err(DeadlineExceeded)` at the deadline, and waiting for it blocks
+/// forever. Where that can happen, also bound the wait on the receiving side:
+///
+/// ```rust,ignore
+/// let timeout = Duration::from_millis(100);
+/// let promise = proxy.with_timeout(timeout).increment_by(2).with_timeout(timeout);
+/// ```
+pub trait DeadlineExt<Msg: HasDeadline>: Proxy<Msg> {
+    /// Send messages through this proxy with the given deadline, checked when they are dispatched
+    fn with_deadline(&self, deadline: Instant) -> WithDeadline<'_, Self> {
+        WithDeadline {
+            proxy: self,
+            deadline,
+        }
+    }
+
+    /// Send messages through this proxy with a deadline `timeout` from now, checked when they are
+    /// dispatched
+    fn with_timeout(&self, timeout: Duration) -> WithDeadline<'_, Self> {
+        self.with_deadline(Instant::now() + timeout)
+    }
+}
+
+impl<Msg: HasDeadline, P: Proxy<Msg> + ?Sized> DeadlineExt<Msg> for P {}
@@ -1,20 +1,85 @@
-#![feature(impl_trait_in_assoc_type)]
-#![feature(try_trait_v2)]
+#![cfg_attr(feature = "nightly", feature(try_trait_v2))]
 #![doc = core::include_str!("../README.md")]
 #![cfg_attr(not(feature = "std"), no_std)]
 
 #[cfg(feature = "support")]
 pub mod support;
 
+#[cfg(feature = "std")]
+pub mod deadline;
+
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
 pub use ctrlgen_derive::ctrlgen;
 
+/// Paths used by the generated code, so it does not depend on the `std` or `alloc` crates being
+/// in scope of the calling crate
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "alloc")]
+    pub use alloc::borrow::ToOwned;
+    #[cfg(feature = "alloc")]
+    pub use alloc::boxed::Box;
+}
+
 pub trait Proxy<Msg> {
     fn send(&self, msg: Msg);
 }
 
+/// A proxy that sends messages asynchronously, for example over a bounded channel that may be full.
+pub trait AsyncProxy<Msg> {
+    fn send_async(&self, msg: Msg) -> impl core::future::Future<Output = ()> + '_;
+}
+
+/// A proxy that routes each message to one of several targets, selected by a key.
+///
+/// Sending may fail, for example if there is no target registered for the given key.
+pub trait RoutingProxy<Key, Msg> {
+    type Error;
+    fn send_to(&self, key: Key, msg: Msg) -> core::result::Result<(), Self::Error>;
+}
+
+/// Implemented for message enums with `#[ctrlgen_coalesce]` methods.
+///
+/// Queues like `support::coalesce` use this to replace a pending message with a newer one
+/// instead of queueing both, when only the latest value matters.
+pub trait Coalesce {
+    /// Returns true if `newer` supersedes `self`, such that `self` can be dropped from a queue
+    /// when `newer` arrives.
+    fn coalesces_with(&self, newer: &Self) -> bool;
+}
+
+/// The lane a message is queued in. Higher lanes are always drained first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// The number of priority lanes
+    pub const COUNT: usize = 3;
+
+    /// The lane index of this priority, where `0` is the highest priority lane
+    pub fn lane(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Implemented for all generated message enums, so any service can be used with a priority queue.
+///
+/// Methods without a `#[ctrlgen_priority(...)]` attribute have [Priority::Normal].
+pub trait Prioritized {
+    fn priority(&self) -> Priority;
+}
+
 pub trait Returnval {
     type Sender<T>;
     type Receiver<T>;
@@ -24,6 +89,97 @@ pub trait Returnval {
     fn create<T>() -> (Self::Sender<T>, Self::Receiver<T>);
     fn send<T>(tx: Self::Sender<T>, msg: T) -> core::result::Result<(), Self::SendError>;
     fn recv<T>(rx: Self::Receiver<T>) -> Self::RecvResult<T>;
+
+    /// Returns true if nobody will receive a value sent on `tx`, for example because the receiver
+    /// (or the `Promise` wrapping it) has been dropped.
+    ///
+    /// Channels that cannot detect this always return `false`.
+    fn is_cancelled<T>(_tx: &Self::Sender<T>) -> bool {
+        false
+    }
+}
+
+/// A return channel for methods that return a sequence of values, declared as `ctrlgen::Stream<T>` or
+/// `impl Iterator<Item = T>`.
+///
+/// Each item is sent as soon as the method's iterator yields it, and the receiving end sees the
+/// stream end when the sender is dropped after the last item.
+pub trait StreamReturnval: Returnval {
+    type StreamSender<T>;
+    type StreamReceiver<T>;
+    type StreamRecvResult<T>;
+
+    fn create_stream<T>() -> (Self::StreamSender<T>, Self::StreamReceiver<T>);
+    fn send_item<T>(
+        tx: &Self::StreamSender<T>,
+        item: T,
+    ) -> core::result::Result<(), <Self as Returnval>::SendError>;
+    fn recv_stream<T>(rx: Self::StreamReceiver<T>) -> Self::StreamRecvResult<T>;
+
+    /// The streaming counterpart of [Returnval::is_cancelled]
+    fn is_stream_cancelled<T>(_tx: &Self::StreamSender<T>) -> bool {
+        false
+    }
+}
+
+/// The declared return type of a method that streams its return values through a
+/// [StreamReturnval]. It has to be named by its `ctrlgen::Stream` path in the method signature.
+///
+/// ```rust,ignore
+/// pub fn scan(&mut self, path: String) -> ctrlgen::Stream<'_, Sample> {
+///     ctrlgen::Stream::new(walk(path).map(Sample::load))
+/// }
+/// ```
+#[cfg(feature = "alloc")]
+pub struct Stream<'a, T> {
+    iter: alloc::boxed::Box<dyn Iterator<Item = T> + 'a>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T> Stream<'a, T> {
+    pub fn new(iter: impl IntoIterator<Item = T> + 'a) -> Self {
+        Self {
+            iter: alloc::boxed::Box::new(iter.into_iter()),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Iterator for Stream<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+}
+
+/// Checks whether the caller is still waiting for the return value of a running method.
+///
+/// A context value of this type, like `context(cancel: ctrlgen::CancellationToken)`, is not passed by
+/// the caller, but provided by the dispatch for each message.
+///
+/// ```rust,ignore
+/// pub async fn scan(&mut self, cancel: CancellationToken<'_>, path: String) -> Vec<Sample> {
+///     for entry in walk(path) {
+///         if cancel.is_cancelled() {
+///             break;
+///         }
+///         ...
+///     }
+/// }
+/// ```
+pub struct CancellationToken<'a> {
+    is_cancelled: &'a dyn Fn() -> bool,
+}
+
+impl<'a> CancellationToken<'a> {
+    pub fn new(is_cancelled: &'a dyn Fn() -> bool) -> Self {
+        Self { is_cancelled }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        (self.is_cancelled)()
+    }
 }
 
 pub trait AsyncReturnval {
@@ -50,53 +206,263 @@ impl IsUnit for () {
 
 pub trait CallMut<Service>: Sized {
     type Error;
-    type Context;
+    /// The values passed to methods besides the message arguments, set with the `context(...)`
+    /// parameter. Contexts may borrow for `'c`, for example `context(buffer: &mut AudioBuffer)`.
+    type Context<'c>;
     fn call_mut_with_ctx(
         self,
         service: &mut Service,
-        context: Self::Context,
+        context: Self::Context<'_>,
     ) -> core::result::Result<(), Self::Error>;
 
     fn call_mut(self, service: &mut Service) -> core::result::Result<(), Self::Error>
     where
-        Self::Context: IsUnit,
+        for<'c> Self::Context<'c>: IsUnit,
     {
         self.call_mut_with_ctx(service, Self::Context::new())
     }
+
+    /// Called once after every batch dispatched through [CallMutBatch].
+    ///
+    /// Generated from the service method marked `#[ctrlgen_batch_hook]`, if any.
+    fn batch_hook(_service: &mut Service) {}
 }
 
+/// Errors collected while dispatching a batch, as `(index, error)` pairs, where `index` is the
+/// position of the failed message in the batch.
+#[cfg(feature = "alloc")]
+pub type BatchErrors<E> = alloc::vec::Vec<(usize, E)>;
+
+/// Dispatch many messages at once.
+///
+/// Messages are called in order, and an error in one message does not stop the rest of the batch.
+/// After the batch, [CallMut::batch_hook] is run once.
+///
+/// The context is cloned for every message, so contexts borrowed mutably need to be dispatched one
+/// message at a time.
+#[cfg(feature = "alloc")]
+pub trait CallMutBatch<Service>: CallMut<Service> {
+    fn call_mut_batch_with_ctx<I>(
+        msgs: I,
+        service: &mut Service,
+        context: Self::Context<'_>,
+    ) -> BatchErrors<Self::Error>
+    where
+        I: IntoIterator<Item = Self>,
+        for<'c> Self::Context<'c>: Clone,
+    {
+        let mut errors = BatchErrors::new();
+        for (i, msg) in msgs.into_iter().enumerate() {
+            if let Err(e) = msg.call_mut_with_ctx(service, context.clone()) {
+                errors.push((i, e));
+            }
+        }
+        Self::batch_hook(service);
+        errors
+    }
+
+    fn call_mut_batch<I>(msgs: I, service: &mut Service) -> BatchErrors<Self::Error>
+    where
+        I: IntoIterator<Item = Self>,
+        for<'c> Self::Context<'c>: IsUnit + Clone,
+    {
+        Self::call_mut_batch_with_ctx(msgs, service, Self::Context::new())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<Service, Msg: CallMut<Service>> CallMutBatch<Service> for Msg {}
+
 pub trait CallMutAsync<Service>: Sized {
     type Error;
-    type Context;
-    type Future<'a>: core::future::Future<Output = core::result::Result<(), Self::Error>> + 'a
+    /// See [CallMut::Context]
+    type Context<'c>;
+    /// The future returned by dispatching a message, borrowing the service for `'a` and the context
+    /// for `'c`
+    type Future<'a, 'c>: core::future::Future<Output = core::result::Result<(), Self::Error>>
     where
-        Service: 'a;
-    fn call_mut_async_with_ctx(
+        Service: 'a,
+        Self: 'a,
+        'c: 'a;
+    fn call_mut_async_with_ctx<'a, 'c: 'a>(
         self,
-        service: &mut Service,
-        context: Self::Context,
-    ) -> Self::Future<'_>;
+        service: &'a mut Service,
+        context: Self::Context<'c>,
+    ) -> Self::Future<'a, 'c>
+    where
+        Self: 'a;
 
-    fn call_mut_async(self, service: &mut Service) -> Self::Future<'_>
+    fn call_mut_async<'a>(self, service: &'a mut Service) -> Self::Future<'a, 'static>
     where
-        Self::Context: IsUnit,
+        Self: 'a,
+        for<'c> Self::Context<'c>: IsUnit,
     {
         self.call_mut_async_with_ctx(service, Self::Context::new())
     }
+
+    /// Called once after every batch dispatched through [CallMutAsyncBatch].
+    ///
+    /// Generated from the service method marked `#[ctrlgen_batch_hook]`, if any.
+    fn batch_hook(_service: &mut Service) {}
 }
 
-impl<T, U: 'static> CallMutAsync<T> for U
+/// A [CallMutAsync] message whose future is `Send`, so it can be dispatched from tasks of
+/// multi-threaded runtimes.
+///
+/// Implemented for [CallMut] messages, and for async messages generated with the `send` parameter.
+pub trait CallMutAsyncSend<Service>: CallMutAsync<Service> + Send {
+    // The futures borrow `service` without spelling out `+ 'a`: with it, spawning a generic
+    // service loop trips over rustc's higher-ranked lifetime checks (rust-lang/rust#100013).
+    fn call_mut_async_send_with_ctx<'a>(
+        self,
+        service: &'a mut Service,
+        context: Self::Context<'a>,
+    ) -> impl core::future::Future<Output = core::result::Result<(), Self::Error>> + Send;
+
+    fn call_mut_async_send<'a>(
+        self,
+        service: &'a mut Service,
+    ) -> impl core::future::Future<Output = core::result::Result<(), Self::Error>> + Send
+    where
+        for<'c> Self::Context<'c>: IsUnit,
+    {
+        self.call_mut_async_send_with_ctx(service, Self::Context::new())
+    }
+}
+
+/// An async message whose `&self` methods can be dispatched through a shared borrow of the
+/// service, so that several of them run concurrently.
+///
+/// Implemented for async messages generated with the `concurrent` parameter. The service loop in
+/// `support::concurrent` uses it.
+pub trait CallSharedAsync<Service>: CallMutAsync<Service> {
+    type SharedFuture<'a>: core::future::Future<Output = core::result::Result<(), Self::Error>>
+    where
+        Service: 'a,
+        Self: 'a;
+
+    /// Dispatches the message if its method takes `&self` and no context values. Other messages
+    /// are returned, to be dispatched with exclusive access to the service.
+    fn call_shared_async<'a>(
+        self,
+        service: &'a Service,
+    ) -> core::result::Result<Self::SharedFuture<'a>, Self>
+    where
+        Self: 'a;
+}
+
+/// The async counterpart of [CallMutBatch].
+#[cfg(feature = "alloc")]
+pub trait CallMutAsyncBatch<Service>: CallMutAsync<Service> {
+    fn call_mut_async_batch_with_ctx<'a, I>(
+        msgs: I,
+        service: &'a mut Service,
+        context: Self::Context<'a>,
+    ) -> impl core::future::Future<Output = BatchErrors<Self::Error>> + 'a
+    where
+        Self: 'a,
+        I: IntoIterator<Item = Self> + 'a,
+        Self::Context<'a>: Clone,
+    {
+        async move {
+            let mut errors = BatchErrors::new();
+            for (i, msg) in msgs.into_iter().enumerate() {
+                if let Err(e) = msg
+                    .call_mut_async_with_ctx(&mut *service, context.clone())
+                    .await
+                {
+                    errors.push((i, e));
+                }
+            }
+            Self::batch_hook(service);
+            errors
+        }
+    }
+
+    fn call_mut_async_batch<'a, I>(
+        msgs: I,
+        service: &'a mut Service,
+    ) -> impl core::future::Future<Output = BatchErrors<Self::Error>> + 'a
+    where
+        Self: 'a,
+        I: IntoIterator<Item = Self> + 'a,
+        for<'c> Self::Context<'c>: IsUnit + Clone,
+    {
+        Self::call_mut_async_batch_with_ctx(msgs, service, Self::Context::new())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<Service, Msg: CallMutAsync<Service>> CallMutAsyncBatch<Service> for Msg {}
+
+impl<T, U> CallMutAsync<T> for U
 where
     U: CallMut<T>,
 {
     type Error = U::Error;
-    type Context = U::Context;
-    type Future<'a>
-        = impl core::future::Future<Output = core::result::Result<(), Self::Error>> + 'a
+    type Context<'c> = U::Context<'c>;
+    type Future<'a, 'c>
+        = CallMutFuture<'a, 'c, T, U>
+    where
+        T: 'a,
+        U: 'a,
+        'c: 'a;
+
+    fn call_mut_async_with_ctx<'a, 'c: 'a>(
+        self,
+        service: &'a mut T,
+        context: Self::Context<'c>,
+    ) -> Self::Future<'a, 'c>
     where
-        T: 'a;
+        Self: 'a,
+    {
+        CallMutFuture {
+            call: Some((self, service, context)),
+        }
+    }
+
+    fn batch_hook(service: &mut T) {
+        U::batch_hook(service)
+    }
+}
+
+impl<T, U> CallMutAsyncSend<T> for U
+where
+    T: Send,
+    U: CallMut<T> + Send,
+    for<'c> U::Context<'c>: Send,
+{
+    fn call_mut_async_send_with_ctx<'a>(
+        self,
+        service: &'a mut T,
+        context: Self::Context<'a>,
+    ) -> impl core::future::Future<Output = core::result::Result<(), Self::Error>> + Send {
+        CallMutFuture {
+            call: Some((self, service, context)),
+        }
+    }
+}
+
+/// The [CallMutAsync::Future] of messages implementing [CallMut]. Calls the message when first
+/// polled.
+pub struct CallMutFuture<'a, 'c, Service, Msg: CallMut<Service>> {
+    call: Option<(Msg, &'a mut Service, Msg::Context<'c>)>,
+}
+
+// The message and context are moved out when polled, and never pinned
+impl<Service, Msg: CallMut<Service>> Unpin for CallMutFuture<'_, '_, Service, Msg> {}
+
+impl<Service, Msg: CallMut<Service>> core::future::Future for CallMutFuture<'_, '_, Service, Msg> {
+    type Output = core::result::Result<(), Msg::Error>;
 
-    fn call_mut_async_with_ctx(self, service: &mut T, context: Self::Context) -> Self::Future<'_> {
-        async { self.call_mut_with_ctx(service, context) }
+    fn poll(
+        mut self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Self::Output> {
+        let (msg, service, context) = self
+            .call
+            .take()
+            .expect("`CallMutFuture` polled after completion");
+        core::task::Poll::Ready(msg.call_mut_with_ctx(service, context))
     }
 }
@@ -0,0 +1,75 @@
+//! A queue that coalesces redundant messages.
+//!
+//! When a message arrives for a `#[ctrlgen_coalesce]` method, and a message for the same method
+//! (with the same key arguments) is still pending, the pending message is replaced in place instead
+//! of appending the new one. This keeps floods of parameter changes from starving the service.
+//!
+//! A replaced message is dropped without being handled, so if it carried a return value sender,
+//! the corresponding receiver will never get a value.
+//!
+//! ```rust,ignore
+//! #[ctrlgen::ctrlgen(pub enum ServiceMsg)]
+//! impl Service {
+//!     #[ctrlgen_coalesce]
+//!     pub fn set_gain(&mut self, gain: f32) { ... }
+//!     #[ctrlgen_coalesce(param)]
+//!     pub fn set_param(&mut self, param: u32, value: f32) { ... }
+//! }
+//!
+//! let (proxy, rx) = coalesce::channel::<ServiceMsg>();
+//! // Service loop:
+//! while let Some(msg) = rx.recv() {
+//!     msg.call_mut(&mut service)?;
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use super::queue;
+use super::queue::Pending;
+use super::queue::QueueProxy;
+use super::queue::QueueReceiver;
+use crate::Coalesce;
+
+/// The pending messages of a coalescing queue, in the order they were first sent
+pub struct Coalescing<Msg>(VecDeque<Msg>);
+
+impl<Msg> Default for Coalescing<Msg> {
+    fn default() -> Self {
+        Self(VecDeque::new())
+    }
+}
+
+impl<Msg: Coalesce> Pending for Coalescing<Msg> {
+    type Msg = Msg;
+
+    fn push(&mut self, msg: Msg) {
+        match self
+            .0
+            .iter_mut()
+            .find(|pending| pending.coalesces_with(&msg))
+        {
+            Some(pending) => *pending = msg,
+            None => self.0.push_back(msg),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Msg> {
+        self.0.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// A proxy that pushes messages onto a coalescing queue
+pub type CoalescingProxy<Msg> = QueueProxy<Coalescing<Msg>>;
+
+/// The receiving end of a coalescing queue
+pub type CoalescingReceiver<Msg> = QueueReceiver<Coalescing<Msg>>;
+
+/// Create a coalescing queue, returning the sending and receiving ends.
+pub fn channel<Msg: Coalesce>() -> (CoalescingProxy<Msg>, CoalescingReceiver<Msg>) {
+    queue::channel()
+}
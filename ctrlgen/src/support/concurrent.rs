@@ -0,0 +1,93 @@
+//! A service loop running the `&self` methods of async messages concurrently.
+//!
+//! Messages generated with the `concurrent` parameter implement [CallSharedAsync]. Their `&self`
+//! methods are dispatched through a shared borrow of the service, and run concurrently, up to a
+//! limit. `&mut self` methods keep exclusive access: the loop waits for the running `&self`
+//! methods to finish, and receives no more messages until the `&mut self` method finished. The
+//! order in which messages start running is the order in which they were received.
+//!
+//! ```rust,ignore
+//! #[ctrlgen::ctrlgen(pub enum IndexMsg, returnval = TokioRetval, concurrent)]
+//! impl Index {
+//!     pub async fn query(&self, query: String) -> Vec<Hit> { ... }
+//!     pub async fn insert(&mut self, document: Document) { ... }
+//! }
+//!
+//! let messages = futures_lite::stream::poll_fn(|cx| rx.poll_recv(cx));
+//! concurrent::serve(&mut index, messages, 16).await?;
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Poll;
+
+use futures_lite::Stream;
+
+use crate::CallSharedAsync;
+use crate::IsUnit;
+
+/// Dispatches the messages of `messages` to `service` until the stream ends, running up to
+/// `limit` `&self` methods at a time.
+///
+/// Returns the first error of dispatching a message. The methods still running are then dropped.
+pub async fn serve<Service, Msg, Messages>(
+    service: &mut Service,
+    messages: Messages,
+    limit: usize,
+) -> Result<(), Msg::Error>
+where
+    Msg: CallSharedAsync<Service>,
+    for<'c> Msg::Context<'c>: IsUnit,
+    Messages: Stream<Item = Msg>,
+{
+    let mut messages = core::pin::pin!(messages);
+    let limit = limit.max(1);
+    let mut ended = false;
+    loop {
+        let exclusive = {
+            let service = &*service;
+            let mut running: Vec<Pin<Box<Msg::SharedFuture<'_>>>> = Vec::new();
+            let mut exclusive = None;
+            core::future::poll_fn(|cx| {
+                let mut i = 0;
+                while i < running.len() {
+                    match running[i].as_mut().poll(cx) {
+                        Poll::Ready(result) => {
+                            drop(running.swap_remove(i));
+                            result?;
+                        }
+                        Poll::Pending => i += 1,
+                    }
+                }
+                while exclusive.is_none() && !ended && running.len() < limit {
+                    match messages.as_mut().poll_next(cx) {
+                        Poll::Ready(Some(msg)) => match msg.call_shared_async(service) {
+                            Ok(future) => {
+                                let mut future = Box::pin(future);
+                                match future.as_mut().poll(cx) {
+                                    Poll::Ready(result) => result?,
+                                    Poll::Pending => running.push(future),
+                                }
+                            }
+                            Err(msg) => exclusive = Some(msg),
+                        },
+                        Poll::Ready(None) => ended = true,
+                        Poll::Pending => break,
+                    }
+                }
+                if running.is_empty() && (exclusive.is_some() || ended) {
+                    Poll::Ready(Ok(exclusive.take()))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await?
+        };
+        match exclusive {
+            Some(msg) => msg.call_mut_async(service).await?,
+            None => return Ok(()),
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use super::FailedToSendRetval;
+use crate::AsyncProxy;
 use crate::Proxy;
+use crate::Returnval;
+use crate::StreamReturnval;
 
 /// A proxy that sends Msg through a [::flume] channel
 pub struct FlumeProxy<Msg> {
@@ -11,8 +15,79 @@ impl<Msg> FlumeProxy<Msg> {
     }
 }
 
+impl<Msg> Clone for FlumeProxy<Msg> {
+    fn clone(&self) -> Self {
+        Self::new(self.sender.clone())
+    }
+}
+
 impl<Msg: core::fmt::Debug> Proxy<Msg> for FlumeProxy<Msg> {
     fn send(&self, msg: Msg) {
         self.sender.send(msg).unwrap()
     }
 }
+
+impl<Msg: core::fmt::Debug> AsyncProxy<Msg> for FlumeProxy<Msg> {
+    async fn send_async(&self, msg: Msg) {
+        self.sender.send_async(msg).await.unwrap()
+    }
+}
+
+/// A return channel using bounded [::flume] channels.
+///
+/// The receiving end is a plain [flume::Receiver], which supports both blocking
+/// ([`recv`](flume::Receiver::recv), [`recv_timeout`](flume::Receiver::recv_timeout)) and async
+/// ([`recv_async`](flume::Receiver::recv_async)) receiving, without depending on a runtime.
+pub struct FlumeRetval;
+
+impl Returnval for FlumeRetval {
+    type Sender<T> = flume::Sender<T>;
+    type Receiver<T> = flume::Receiver<T>;
+    type SendError = FailedToSendRetval;
+
+    type RecvResult<T> = flume::Receiver<T>;
+
+    fn create<T>() -> (Self::Sender<T>, Self::Receiver<T>) {
+        flume::bounded(1)
+    }
+
+    fn recv<T>(rx: Self::Receiver<T>) -> Self::RecvResult<T> {
+        rx
+    }
+
+    fn send<T>(tx: Self::Sender<T>, msg: T) -> core::result::Result<(), Self::SendError> {
+        tx.send(msg).map_err(|_| FailedToSendRetval)
+    }
+
+    fn is_cancelled<T>(tx: &Self::Sender<T>) -> bool {
+        tx.is_disconnected()
+    }
+}
+
+/// Streams are sent over an unbounded [::flume] channel. The receiving [flume::Receiver] can be
+/// iterated, or polled with [`try_recv`](flume::Receiver::try_recv).
+impl StreamReturnval for FlumeRetval {
+    type StreamSender<T> = flume::Sender<T>;
+    type StreamReceiver<T> = flume::Receiver<T>;
+
+    type StreamRecvResult<T> = flume::Receiver<T>;
+
+    fn create_stream<T>() -> (Self::StreamSender<T>, Self::StreamReceiver<T>) {
+        flume::unbounded()
+    }
+
+    fn send_item<T>(
+        tx: &Self::StreamSender<T>,
+        item: T,
+    ) -> core::result::Result<(), Self::SendError> {
+        tx.send(item).map_err(|_| FailedToSendRetval)
+    }
+
+    fn recv_stream<T>(rx: Self::StreamReceiver<T>) -> Self::StreamRecvResult<T> {
+        rx
+    }
+
+    fn is_stream_cancelled<T>(tx: &Self::StreamSender<T>) -> bool {
+        tx.is_disconnected()
+    }
+}
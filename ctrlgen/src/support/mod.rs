@@ -1,9 +1,13 @@
 use core::cell::RefCell;
 use core::convert::Infallible;
-use std::marker::PhantomData;
+use core::marker::PhantomData;
 
+use crate::CallMut;
+use crate::IsUnit;
 use crate::Proxy;
 use crate::Returnval;
+#[cfg(feature = "alloc")]
+use crate::StreamReturnval;
 
 #[cfg(feature = "flume")]
 pub mod flume;
@@ -11,14 +15,31 @@ pub mod flume;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
-#[cfg(feature = "tokio")]
+#[cfg(feature = "promise")]
+pub mod oneshot;
+
+#[cfg(feature = "promise")]
 pub mod promise;
 
+#[cfg(feature = "promise")]
+mod timer;
+
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+
+pub mod coalesce;
+pub mod priority;
+pub mod queue;
+pub mod router;
+
+#[cfg(feature = "std")]
+pub mod std;
+
 #[derive(Debug)]
 pub struct FailedToSendRetval;
-impl std::error::Error for FailedToSendRetval {}
-impl std::fmt::Display for FailedToSendRetval {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ::std::error::Error for FailedToSendRetval {}
+impl core::fmt::Display for FailedToSendRetval {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str("Failed to send return value")
     }
 }
@@ -44,6 +65,42 @@ impl<Msg, F: Fn(Msg)> Proxy<Msg> for FnProxy<Msg, F> {
     }
 }
 
+/// A Proxy that calls every message on a service right away, instead of sending it anywhere.
+///
+/// Works with any message type calling the service, including borrowed `ref enum`s. Errors sending
+/// return values are ignored. Calling the proxy from inside a service method panics.
+pub struct DirectProxy<S> {
+    service: RefCell<S>,
+}
+
+impl<S> DirectProxy<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service: RefCell::new(service),
+        }
+    }
+
+    pub fn service_mut(&mut self) -> &mut S {
+        self.service.get_mut()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.service.into_inner()
+    }
+}
+
+impl<S, Msg> Proxy<Msg> for DirectProxy<S>
+where
+    Msg: CallMut<S>,
+    for<'c> Msg::Context<'c>: IsUnit,
+{
+    fn send(&self, msg: Msg) {
+        let _ = msg.call_mut(&mut self.service.borrow_mut());
+    }
+}
+
+#[cfg(feature = "alloc")]
+use alloc::collections::VecDeque;
 #[cfg(feature = "alloc")]
 use alloc::rc::Rc;
 
@@ -71,4 +128,37 @@ impl Returnval for LocalRetval {
         tx.replace(Some(msg));
         Ok(())
     }
+
+    fn is_cancelled<T>(tx: &Self::Sender<T>) -> bool {
+        Rc::strong_count(tx) == 1
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl StreamReturnval for LocalRetval {
+    type StreamSender<T> = Rc<RefCell<VecDeque<T>>>;
+    type StreamReceiver<T> = Rc<RefCell<VecDeque<T>>>;
+
+    type StreamRecvResult<T> = Self::StreamReceiver<T>;
+
+    fn create_stream<T>() -> (Self::StreamSender<T>, Self::StreamReceiver<T>) {
+        let x = Rc::new(RefCell::new(VecDeque::new()));
+        (x.clone(), x)
+    }
+
+    fn send_item<T>(
+        tx: &Self::StreamSender<T>,
+        item: T,
+    ) -> core::result::Result<(), Self::SendError> {
+        tx.borrow_mut().push_back(item);
+        Ok(())
+    }
+
+    fn recv_stream<T>(rx: Self::StreamReceiver<T>) -> Self::StreamRecvResult<T> {
+        rx
+    }
+
+    fn is_stream_cancelled<T>(tx: &Self::StreamSender<T>) -> bool {
+        Rc::strong_count(tx) == 1
+    }
 }
@@ -0,0 +1,150 @@
+//! A runtime-agnostic oneshot channel, used by [Promise](super::promise::Promise).
+//!
+//! Unlike the oneshot channels of async runtimes, this does not depend on any executor, so the
+//! receiver can be awaited under tokio, smol, async-std or a plain `block_on`.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+
+struct State<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+type Shared<T> = Arc<Mutex<State<T>>>;
+
+/// Lock the state, also after a panic while it was locked, so that dropping either end while
+/// unwinding does not panic again
+fn lock<T>(shared: &Shared<T>) -> MutexGuard<'_, State<T>> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Create a oneshot channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value: None,
+        waker: None,
+        sender_alive: true,
+        receiver_alive: true,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// The error returned by [Receiver::try_recv]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The value has not been sent yet
+    Empty,
+    /// The sender was dropped without sending a value, or the value has already been received
+    Closed,
+}
+
+/// The sending end of a oneshot channel
+pub struct Sender<T> {
+    shared: Shared<T>,
+}
+
+impl<T> Sender<T> {
+    /// Send the value, waking the receiver.
+    ///
+    /// Returns the value back if the receiver has been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = lock(&self.shared);
+        if !state.receiver_alive {
+            return Err(value);
+        }
+        state.value = Some(value);
+        // Wake without holding the lock, as the waker may poll the receiver right away
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns true if the receiver has been dropped, so sending would fail
+    pub fn is_cancelled(&self) -> bool {
+        !lock(&self.shared).receiver_alive
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.shared);
+        state.sender_alive = false;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> core::fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// The receiving end of a oneshot channel
+pub struct Receiver<T> {
+    shared: Shared<T>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the value if it has been sent
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = lock(&self.shared);
+        match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Closed),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        lock(&self.shared).receiver_alive = false;
+    }
+}
+
+impl<T> core::fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    /// `None` if the sender was dropped without sending a value
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.shared);
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Some(value));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(None);
+        }
+        match &mut state.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => state.waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
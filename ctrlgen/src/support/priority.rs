@@ -0,0 +1,67 @@
+//! A queue with one lane per [Priority].
+//!
+//! Messages are received from the highest non-empty lane first, and in order within a lane.
+//! This way, a `#[ctrlgen_priority(high)]` method like `stop()` never waits behind a backlog of
+//! normal priority messages.
+//!
+//! ```rust,ignore
+//! #[ctrlgen::ctrlgen(pub enum ServiceMsg)]
+//! impl Service {
+//!     #[ctrlgen_priority(high)]
+//!     pub fn stop(&mut self) { ... }
+//!     pub fn set_param(&mut self, param: u32, value: f32) { ... }
+//! }
+//!
+//! let (proxy, rx) = priority::channel::<ServiceMsg>();
+//! // Service loop:
+//! while let Some(msg) = rx.recv() {
+//!     msg.call_mut(&mut service)?;
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use super::queue;
+use super::queue::Pending;
+use super::queue::QueueProxy;
+use super::queue::QueueReceiver;
+use crate::Prioritized;
+use crate::Priority;
+
+/// The pending messages of a priority queue, in one lane per [Priority]
+pub struct Lanes<Msg>([VecDeque<Msg>; Priority::COUNT]);
+
+impl<Msg> Default for Lanes<Msg> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<Msg: Prioritized> Pending for Lanes<Msg> {
+    type Msg = Msg;
+
+    fn push(&mut self, msg: Msg) {
+        let lane = msg.priority().lane();
+        self.0[lane].push_back(msg);
+    }
+
+    /// Take the next message from the highest non-empty lane
+    fn pop(&mut self) -> Option<Msg> {
+        self.0.iter_mut().find_map(|lane| lane.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// A proxy that pushes messages onto the lane of their priority
+pub type PriorityProxy<Msg> = QueueProxy<Lanes<Msg>>;
+
+/// The receiving end of a priority queue
+pub type PriorityReceiver<Msg> = QueueReceiver<Lanes<Msg>>;
+
+/// Create a priority queue, returning the sending and receiving ends.
+pub fn channel<Msg: Prioritized>() -> (PriorityProxy<Msg>, PriorityReceiver<Msg>) {
+    queue::channel()
+}
@@ -1,13 +1,176 @@
-use futures_lite::pin;
+use std::cell::OnceCell;
+use std::cell::RefCell;
+#[cfg(feature = "nightly")]
 use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::TryLockError;
+use std::task::Context;
 use std::task::Poll;
-use tokio::sync::oneshot;
+use std::task::RawWaker;
+use std::task::RawWakerVTable;
+use std::task::Wake;
+use std::task::Waker;
+use std::time::Duration;
+use std::time::Instant;
 
-#[derive(Debug)]
-enum Inner<T> {
+use futures_lite::future::poll_fn;
+
+use super::oneshot;
+use super::timer::Timer;
+
+/// Spawns futures onto an executor.
+///
+/// Used by [Promise::spawn_with], [Promise::map_promise_with] and [Promise::then_promise_with], so
+/// promises can be used with any async runtime.
+///
+/// ```rust,ignore
+/// struct SmolSpawner;
+/// impl Spawner for SmolSpawner {
+///     fn spawn<F>(&self, fut: F)
+///     where
+///         F: std::future::Future<Output = ()> + Send + 'static,
+///     {
+///         smol::spawn(fut).detach();
+///     }
+/// }
+/// ```
+pub trait Spawner {
+    fn spawn<F>(&self, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static;
+}
+
+/// A [Spawner] that runs each future to completion on a new thread, without any async runtime.
+pub struct ThreadSpawner;
+
+impl Spawner for ThreadSpawner {
+    fn spawn<F>(&self, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        std::thread::spawn(move || futures_lite::future::block_on(fut));
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = Option<T>> + Send>>;
+
+/// Where a promise receives its value from, until it resolved
+enum Source<T> {
     Pending(oneshot::Receiver<T>),
-    Ready(T),
-    Empty,
+    /// Resolved by polling a future. Used by the combinators, so they can be polled through
+    /// [Promise::get] without spawning a task.
+    Deferred(BoxFuture<T>),
+    /// Polled by the notifier set with [Promise::set_notify]. `waker` wakes `notifier`.
+    Notifying {
+        notifier: Arc<Notifier<T>>,
+        waker: Waker,
+    },
+    Resolved,
+}
+
+impl<T> Source<T> {
+    /// Poll for the value, registering `cx` to be woken when it can make progress
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self {
+            Source::Pending(rx) => Pin::new(rx).poll(cx),
+            Source::Deferred(fut) => fut.as_mut().poll(cx),
+            Source::Notifying { notifier, waker } => loop {
+                let mut state = notifier.lock();
+                if let Some(value) = state.value.take() {
+                    return Poll::Ready(value);
+                }
+                state.task = Some(cx.waker().clone());
+                drop(state);
+                // A wake while the state was locked here did not poll the source, so poll it now
+                if !notifier.woken.load(Ordering::SeqCst) {
+                    return Poll::Pending;
+                }
+                waker.wake_by_ref();
+            },
+            Source::Resolved => Poll::Ready(None),
+        }
+    }
+}
+
+/// The waker of a promise with a notification hook. When woken, it polls the source of the
+/// promise itself, and calls the hook once the promise resolved.
+struct Notifier<T> {
+    state: Mutex<NotifierState<T>>,
+    /// Set by wakes, so a wake while the source is being polled makes it be polled again
+    woken: AtomicBool,
+    notify: Box<dyn Fn() + Send + Sync>,
+}
+
+struct NotifierState<T> {
+    /// Replaced by [Source::Resolved] when the promise resolved, or when the source was moved to
+    /// another notifier
+    source: Source<T>,
+    /// The resolved value, until the promise takes it
+    value: Option<Option<T>>,
+    /// The waker of the task awaiting the promise, or of the last [Promise::get]
+    task: Option<Waker>,
+}
+
+impl<T> Notifier<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, NotifierState<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Send + 'static> Wake for Notifier<T> {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        loop {
+            let mut state = match self.state.try_lock() {
+                Ok(state) => state,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                // Whoever polls the source sees `woken` afterwards
+                Err(TryLockError::WouldBlock) => return,
+            };
+            if !self.woken.swap(false, Ordering::SeqCst) {
+                drop(state);
+                if self.woken.load(Ordering::SeqCst) {
+                    continue;
+                }
+                return;
+            }
+            if matches!(state.source, Source::Resolved) {
+                return;
+            }
+            let waker = Waker::from(self.clone());
+            if let Poll::Ready(value) = state.source.poll(&mut Context::from_waker(&waker)) {
+                state.source = Source::Resolved;
+                state.value = Some(value);
+                let task = state.task.take();
+                drop(state);
+                (self.notify)();
+                if let Some(task) = task {
+                    task.wake();
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(std::ptr::null(), &VTABLE),
+        |_| (),
+        |_| (),
+        |_| (),
+    );
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
 }
 
 /// A promise represents a value that may not yet have been received.
@@ -17,10 +180,18 @@ enum Inner<T> {
 /// it is useful anywhere where one may want to start an async operation from a non-async context,
 /// and then check if the result is available on subsequent passes.
 ///
-/// This struct uses [tokio::sync::oneshot] to receive the item.
-/// As opposed to the [tokio::sync::oneshot::Receiver], the promise retains
+/// This struct uses a runtime-agnostic [oneshot] channel to receive the item.
+/// As opposed to the [oneshot::Receiver], the promise retains
 /// the item after it has been received.
 ///
+/// A promise can be sent to another thread, but not shared between threads, as it polls its
+/// channel through `&self`:
+///
+/// ```rust,compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<ctrlgen::support::promise::Promise<u32>>();
+/// ```
+///
 /// ## Example
 /// ```rust,ignore
 /// async fn long_async_task() -> i32 {
@@ -28,7 +199,7 @@ enum Inner<T> {
 ///     return 42;
 /// }
 ///
-/// let promise = Promise::spawn(long_async_task());
+/// let promise = Promise::spawn_with(&ThreadSpawner, long_async_task());
 /// // In GUI loop:
 /// if let Some(x) = promise.get() {
 ///   ui.label(format!("Result: {x}"))
@@ -39,9 +210,9 @@ enum Inner<T> {
 /// This is similar in idea and implementaiton to [poll-promise](https://lib.rs/crates/poll-promise),
 /// with a few minor differences:
 ///
-///  - This uses [tokio::sync::oneshot] instead of [std::sync::mpsc], which should be better optimized
-///    for this usecase. However, this means it is only available with tokio, and furthermore it is only
-///    implemented for use on the tokio runtime.
+///  - This uses a dedicated [oneshot] channel instead of [std::sync::mpsc], which should be better optimized
+///    for this usecase. It does not depend on any runtime, and futures are spawned through a pluggable
+///    [Spawner].
 ///
 ///  - This promise provides an `empty` state along with `pending` and `ready`, which can be useful to
 ///    represent promises that will never be resolved, for example if the sender is closed. This also
@@ -52,37 +223,50 @@ enum Inner<T> {
 ///  - This promise implements [std::future::Future], and can thus be `.await`ed. This makes functions
 ///    that return a promise useful in async contexts as well.
 ///
-///  - This promise implements [std::ops::FromResidual] for [Result] and [Option], allowing you to use the
-///    `?` operator in functions that return [Result] or [Option].
+///  - Polling code can be notified when the promise resolves, with [Promise::set_notify] or
+///    [Promise::on_ready], instead of checking it on every pass.
+///
+///  - With the `nightly` feature, this promise implements `std::ops::FromResidual` for [Result] and
+///    [Option], allowing you to use the `?` operator in functions that return [Result] or [Option].
 #[must_use = "Promises should not be discarded"]
-#[derive(Debug)]
 pub struct Promise<T> {
-    inner: std::cell::UnsafeCell<Inner<T>>,
+    /// Set once the promise resolved, to `Some` if it received a value. It is only changed
+    /// through `&mut self` afterwards, so [Promise::get] can hand out references into it.
+    value: OnceCell<Option<T>>,
+    source: RefCell<Source<T>>,
 }
 
 /// The type used to send the result to a promise
 pub type Sender<T> = oneshot::Sender<T>;
 
 impl<T> Promise<T> {
-    /// Construct a promise from the channel it will receive the value on
-    pub fn new(rx: oneshot::Receiver<T>) -> Self {
+    fn with_source(source: Source<T>) -> Self {
         Self {
-            inner: std::cell::UnsafeCell::new(Inner::Pending(rx)),
+            value: OnceCell::new(),
+            source: RefCell::new(source),
         }
     }
 
-    /// Construct a promise containing a value
-    pub fn ready(val: T) -> Self {
+    fn resolved(value: Option<T>) -> Self {
         Self {
-            inner: std::cell::UnsafeCell::new(Inner::Ready(val)),
+            value: OnceCell::from(value),
+            source: RefCell::new(Source::Resolved),
         }
     }
 
+    /// Construct a promise from the channel it will receive the value on
+    pub fn new(rx: oneshot::Receiver<T>) -> Self {
+        Self::with_source(Source::Pending(rx))
+    }
+
+    /// Construct a promise containing a value
+    pub fn ready(val: T) -> Self {
+        Self::resolved(Some(val))
+    }
+
     /// An empty promise will always resolve to no value.
     pub fn empty() -> Self {
-        Self {
-            inner: std::cell::UnsafeCell::new(Inner::Empty),
-        }
+        Self::resolved(None)
     }
 
     /// Create a promise and its corresponding sender
@@ -91,75 +275,251 @@ impl<T> Promise<T> {
         (tx, Self::new(rx))
     }
 
-    /// Spawn a future on the tokio runtime, returning a promise to its result
-    pub fn spawn<Fut>(fut: Fut) -> Self
+    /// Spawn a future using `spawner`, returning a promise to its result
+    pub fn spawn_with<S, Fut>(spawner: &S, fut: Fut) -> Self
     where
+        S: Spawner + ?Sized,
         Fut: std::future::Future<Output = T> + Send + 'static,
         T: Send + 'static,
     {
         let (tx, res) = Promise::channel();
-        tokio::spawn(async { tx.send(fut.await) });
+        spawner.spawn(async {
+            let _ = tx.send(fut.await);
+        });
         res
     }
 
-    /// Check if a value is ready in the promise. If this is true,
+    /// Spawn a future on the tokio runtime, returning a promise to its result
+    #[cfg(feature = "tokio")]
+    pub fn spawn<Fut>(fut: Fut) -> Self
+    where
+        Fut: std::future::Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        Self::spawn_with(&super::tokio::TokioSpawner, fut)
+    }
+
+    /// Check if a value is ready in the promise. If this is true, [Promise::get] returns it.
     pub fn is_ready(&self) -> bool {
         self.check_rx();
-        matches!(unsafe { &*self.inner.get() }, Inner::Ready(_))
+        matches!(self.value.get(), Some(Some(_)))
     }
 
     /// Check if the promise is empty, i.e. will never resolve
     pub fn is_empty(&self) -> bool {
         self.check_rx();
-        matches!(unsafe { &*self.inner.get() }, Inner::Empty)
+        matches!(self.value.get(), Some(None))
     }
 
     pub fn get(&self) -> Option<&T> {
         self.check_rx();
-        match unsafe { &*self.inner.get() } {
-            Inner::Ready(x) => Some(x),
-            Inner::Pending(_) => None,
-            Inner::Empty => None,
-        }
+        self.value.get()?.as_ref()
     }
 
     /// Take the value out of the promise, leaving an empty promise in its place
     pub fn take(&mut self) -> Option<T> {
         self.check_rx();
-        if self.is_ready() {
-            if let Inner::Ready(x) =
-                std::mem::replace(unsafe { &mut *self.inner.get() }, Inner::Empty)
-            {
-                return Some(x);
-            }
+        self.value.get_mut()?.take()
+    }
+
+    /// Call `notify` when the promise resolves, from the thread that sends the value (or drops
+    /// the sender). Replaces a hook set before. If the promise already resolved, `notify` is called
+    /// right away.
+    ///
+    /// This way, a GUI polling the promise with [Promise::get] only needs to repaint once the
+    /// value arrived:
+    ///
+    /// ```rust,ignore
+    /// let ctx = ui.ctx().clone();
+    /// promise.set_notify(move || ctx.request_repaint());
+    /// ```
+    pub fn set_notify(&mut self, notify: impl Fn() + Send + Sync + 'static)
+    where
+        T: Send + 'static,
+    {
+        self.check_rx();
+        if self.value.get().is_some() {
+            notify();
+            return;
         }
-        None
+        let source = match std::mem::replace(self.source.get_mut(), Source::Resolved) {
+            Source::Notifying {
+                notifier: previous, ..
+            } => {
+                let mut state = previous.lock();
+                if let Some(value) = state.value.take() {
+                    drop(state);
+                    let _ = self.value.set(value);
+                    notify();
+                    return;
+                }
+                std::mem::replace(&mut state.source, Source::Resolved)
+            }
+            source => source,
+        };
+        let notifier = Arc::new(Notifier {
+            state: Mutex::new(NotifierState {
+                source,
+                value: None,
+                task: None,
+            }),
+            woken: AtomicBool::new(false),
+            notify: Box::new(notify),
+        });
+        let waker = Waker::from(notifier.clone());
+        // Poll the source once, so it wakes the notifier
+        waker.wake_by_ref();
+        *self.source.get_mut() = Source::Notifying { notifier, waker };
     }
 
-    fn check_rx(&self) {
-        if let Inner::Pending(rx) = unsafe { &mut *self.inner.get() } {
-            match rx.try_recv() {
-                Ok(x) => unsafe { *self.inner.get() = Inner::Ready(x) },
-                Err(oneshot::error::TryRecvError::Empty) => (),
-                Err(oneshot::error::TryRecvError::Closed) => unsafe {
-                    *self.inner.get() = Inner::Empty
-                },
+    /// Call `callback` once the promise resolves. See [Promise::set_notify].
+    pub fn on_ready(mut self, callback: impl FnOnce() + Send + 'static) -> Self
+    where
+        T: Send + 'static,
+    {
+        let callback = Mutex::new(Some(callback));
+        self.set_notify(move || {
+            let callback = callback
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            if let Some(callback) = callback {
+                callback()
             }
+        });
+        self
+    }
+
+    fn check_rx(&self) {
+        if self.value.get().is_some() {
+            return;
+        }
+        // Deferred futures are `Send + 'static`, so they cannot reach this promise while it polls
+        // them. Still, a promise that is already being polled is left alone.
+        let Ok(mut source) = self.source.try_borrow_mut() else {
+            return;
+        };
+        let waker = noop_waker();
+        if let Poll::Ready(value) = source.poll(&mut Context::from_waker(&waker)) {
+            *source = Source::Resolved;
+            let _ = self.value.set(value);
+        }
+    }
+
+    /// Try to resolve a pending promise, registering `cx` to be woken when it can make progress
+    fn poll_resolve(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.value.get().is_none() {
+            let source = self.source.get_mut();
+            let value = futures_lite::ready!(source.poll(cx));
+            *source = Source::Resolved;
+            let _ = self.value.set(value);
         }
+        Poll::Ready(())
     }
 
-    /// Map a promise through a function.
+    /// A promise that resolves by polling `fut`
+    fn deferred<Fut>(fut: Fut) -> Self
+    where
+        Fut: Future<Output = Option<T>> + Send + 'static,
+    {
+        Self::with_source(Source::Deferred(Box::pin(fut)))
+    }
+
+    /// A promise that resolves once all `promises` have resolved, containing their values in order.
+    ///
+    /// If any of the promises is empty, the resulting promise is empty as well.
+    pub fn join_all(mut promises: Vec<Promise<T>>) -> Promise<Vec<T>>
+    where
+        T: Send + 'static,
+    {
+        Promise::deferred(poll_fn(move |cx| {
+            let mut pending = false;
+            for promise in &mut promises {
+                if promise.poll_resolve(cx).is_pending() {
+                    pending = true;
+                } else if promise.is_empty() {
+                    return Poll::Ready(None);
+                }
+            }
+            if pending {
+                return Poll::Pending;
+            }
+            Poll::Ready(promises.iter_mut().map(Promise::take).collect())
+        }))
+    }
+
+    /// A promise that resolves to the value of whichever of `promises` resolves first.
+    ///
+    /// The promise is empty if all of `promises` are empty.
+    pub fn select(mut promises: Vec<Promise<T>>) -> Promise<T>
+    where
+        T: Send + 'static,
+    {
+        Promise::deferred(poll_fn(move |cx| {
+            let mut pending = false;
+            for promise in &mut promises {
+                if promise.poll_resolve(cx).is_pending() {
+                    pending = true;
+                } else if let Some(x) = promise.take() {
+                    return Poll::Ready(Some(x));
+                }
+            }
+            if pending {
+                Poll::Pending
+            } else {
+                Poll::Ready(None)
+            }
+        }))
+    }
+
+    /// A promise that resolves to the value of this one, or becomes empty if `timeout` expires first.
+    ///
+    /// The deadline is checked whenever the promise is polled. Tasks awaiting it are woken at the
+    /// deadline by a timer thread shared by all promises, so no async runtime timer is needed.
+    pub fn with_timeout(mut self, timeout: Duration) -> Promise<T>
+    where
+        T: Send + 'static,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut timer = None;
+        Promise::deferred(poll_fn(move |cx| {
+            if self.poll_resolve(cx).is_ready() {
+                return Poll::Ready(self.take());
+            }
+            if Instant::now() >= deadline {
+                return Poll::Ready(None);
+            }
+            timer
+                .get_or_insert_with(|| Timer::new(deadline))
+                .register(cx.waker());
+            Poll::Pending
+        }))
+    }
+
+    /// A promise that resolves to the value of this one, or to `T::default()` if this one is empty.
+    pub fn or_default(mut self) -> Promise<T>
+    where
+        T: Default + Send + 'static,
+    {
+        Promise::deferred(poll_fn(move |cx| {
+            futures_lite::ready!(self.poll_resolve(cx));
+            Poll::Ready(Some(self.take().unwrap_or_default()))
+        }))
+    }
+
+    /// Map a promise through a function, spawning the mapping task using `spawner`.
     ///
     /// Only use this when a promise type is needed as result. Otherwise, use [FutureExt::map] directly
     /// as it is more performant.
-    pub fn map_promise<U, F>(self, f: F) -> Promise<U>
+    pub fn map_promise_with<S, U, F>(self, spawner: &S, f: F) -> Promise<U>
     where
+        S: Spawner + ?Sized,
         T: Send + 'static,
         F: FnOnce(T) -> U + Send + 'static,
         U: Send + 'static,
     {
         let (tx, res) = Promise::channel();
-        tokio::spawn(async {
+        spawner.spawn(async {
             if let Some(x) = self.await {
                 let _ = tx.send(f(x));
             }
@@ -169,15 +529,16 @@ impl<T> Promise<T> {
 
     /// Only use this when a promise type is needed as result. Otherwise, use [FutureExt::then] directly
     /// as it is more performant.
-    pub fn then_promise<Fut: std::future::Future, F>(self, f: F) -> Promise<Fut::Output>
+    pub fn then_promise_with<S, Fut, F>(self, spawner: &S, f: F) -> Promise<Fut::Output>
     where
+        S: Spawner + ?Sized,
         T: Send + 'static,
         F: FnOnce(T) -> Fut + Send + 'static,
-        Fut: Send,
+        Fut: std::future::Future + Send,
         Fut::Output: Send + 'static,
     {
         let (tx, res) = Promise::channel();
-        tokio::spawn(async {
+        spawner.spawn(async {
             if let Some(x) = self.await {
                 let _ = tx.send(f(x).await);
             }
@@ -185,6 +546,29 @@ impl<T> Promise<T> {
         res
     }
 
+    /// Map a promise through a function on the tokio runtime. See [Promise::map_promise_with].
+    #[cfg(feature = "tokio")]
+    pub fn map_promise<U, F>(self, f: F) -> Promise<U>
+    where
+        T: Send + 'static,
+        F: FnOnce(T) -> U + Send + 'static,
+        U: Send + 'static,
+    {
+        self.map_promise_with(&super::tokio::TokioSpawner, f)
+    }
+
+    /// See [Promise::then_promise_with].
+    #[cfg(feature = "tokio")]
+    pub fn then_promise<Fut, F>(self, f: F) -> Promise<Fut::Output>
+    where
+        T: Send + 'static,
+        F: FnOnce(T) -> Fut + Send + 'static,
+        Fut: std::future::Future + Send,
+        Fut::Output: Send + 'static,
+    {
+        self.then_promise_with(&super::tokio::TokioSpawner, f)
+    }
+
     /// Block the current thread, waiting for the promise to be resolved
     pub fn block_on(self) -> Option<T> {
         futures_lite::future::block_on(self)
@@ -192,13 +576,18 @@ impl<T> Promise<T> {
 }
 
 // Make Promise<Result> work with the ? operator
+#[cfg(feature = "nightly")]
 impl<T, E, F: From<E>> std::ops::FromResidual<Result<Infallible, E>> for Promise<Result<T, F>> {
     fn from_residual(residual: Result<Infallible, E>) -> Self {
-        Self::ready(Err(F::from(unsafe { residual.unwrap_err_unchecked() })))
+        match residual {
+            Ok(never) => match never {},
+            Err(e) => Self::ready(Err(F::from(e))),
+        }
     }
 }
 
 // Make Promise<Option<T>> work with the ? operator
+#[cfg(feature = "nightly")]
 impl<T> std::ops::FromResidual<Option<Infallible>> for Promise<Option<T>> {
     fn from_residual(_: Option<Infallible>) -> Self {
         Self::ready(None)
@@ -213,13 +602,21 @@ impl<T> std::future::Future for Promise<T> {
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
-        match unsafe { &mut *self.inner.get() } {
-            Inner::Ready(x) => Poll::Ready(Some(unsafe { std::ptr::read(x as *mut T) })),
-            Inner::Empty => Poll::Ready(None),
-            Inner::Pending(rx) => {
-                pin!(rx);
-                rx.poll(cx).map(|x| x.ok())
-            }
+        let this = self.get_mut();
+        futures_lite::ready!(this.poll_resolve(cx));
+        Poll::Ready(this.take())
+    }
+}
+
+// The value is never pinned, and deferred futures are boxed.
+impl<T> Unpin for Promise<T> {}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for Promise<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.value.get() {
+            Some(Some(x)) => f.debug_tuple("Ready").field(x).finish(),
+            Some(None) => f.write_str("Empty"),
+            None => f.write_str("Pending"),
         }
     }
 }
@@ -0,0 +1,165 @@
+//! A blocking multi-producer queue, generic over how pending messages are stored.
+//!
+//! The [Pending] storage decides where a sent message is inserted and which message is received
+//! next. [coalesce](super::coalesce) and [priority](super::priority) are built on it.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Proxy;
+
+/// The pending messages of a queue
+pub trait Pending: Default {
+    type Msg;
+
+    /// Insert a sent message
+    fn push(&mut self, msg: Self::Msg);
+    /// Take the message to be received next
+    fn pop(&mut self) -> Option<Self::Msg>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Messages received in the order they were sent
+impl<Msg> Pending for VecDeque<Msg> {
+    type Msg = Msg;
+
+    fn push(&mut self, msg: Msg) {
+        self.push_back(msg)
+    }
+
+    fn pop(&mut self) -> Option<Msg> {
+        self.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+}
+
+struct Queue<P> {
+    pending: P,
+    proxies: usize,
+}
+
+struct Shared<P> {
+    queue: Mutex<Queue<P>>,
+    available: Condvar,
+}
+
+/// Create a queue storing its pending messages in `P`, returning the sending and receiving ends.
+pub fn channel<P: Pending>() -> (QueueProxy<P>, QueueReceiver<P>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            pending: P::default(),
+            proxies: 1,
+        }),
+        available: Condvar::new(),
+    });
+    (
+        QueueProxy {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// A proxy that pushes messages onto a queue
+pub struct QueueProxy<P> {
+    shared: Arc<Shared<P>>,
+}
+
+impl<P> Clone for QueueProxy<P> {
+    fn clone(&self) -> Self {
+        self.shared.queue.lock().unwrap().proxies += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<P> Drop for QueueProxy<P> {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().proxies -= 1;
+        // Wake the receiver, so it can notice if this was the last proxy.
+        self.shared.available.notify_all();
+    }
+}
+
+impl<P: Pending> Proxy<P::Msg> for QueueProxy<P> {
+    fn send(&self, msg: P::Msg) {
+        self.shared.queue.lock().unwrap().pending.push(msg);
+        self.shared.available.notify_one();
+    }
+}
+
+/// The receiving end of a queue
+pub struct QueueReceiver<P> {
+    shared: Arc<Shared<P>>,
+}
+
+impl<P: Pending> QueueReceiver<P> {
+    /// Take the next message if one is pending
+    pub fn try_recv(&self) -> Option<P::Msg> {
+        self.shared.queue.lock().unwrap().pending.pop()
+    }
+
+    /// Block until a message is available.
+    ///
+    /// Returns `None` once the queue is empty and all proxies have been dropped.
+    pub fn recv(&self) -> Option<P::Msg> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(msg) = queue.pending.pop() {
+                return Some(msg);
+            }
+            if queue.proxies == 0 {
+                return None;
+            }
+            queue = self.shared.available.wait(queue).unwrap();
+        }
+    }
+
+    /// Block until a message is available or `timeout` has passed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<P::Msg> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(msg) = queue.pending.pop() {
+                return Some(msg);
+            }
+            let now = Instant::now();
+            if queue.proxies == 0 || now >= deadline {
+                return None;
+            }
+            queue = self
+                .shared
+                .available
+                .wait_timeout(queue, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Take all pending messages, in the order they would be received
+    pub fn drain(&self) -> impl Iterator<Item = P::Msg> {
+        let mut pending = std::mem::take(&mut self.shared.queue.lock().unwrap().pending);
+        std::iter::from_fn(move || pending.pop())
+    }
+
+    /// The number of pending messages
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,90 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::Proxy;
+use crate::RoutingProxy;
+
+/// Returned when a [Router] has no proxy registered for a key. Contains the key.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownRoute<K>(pub K);
+impl<K: core::fmt::Debug> std::error::Error for UnknownRoute<K> {}
+impl<K: core::fmt::Debug> std::fmt::Display for UnknownRoute<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No route registered for key {:?}", self.0)
+    }
+}
+
+/// A proxy that forwards messages to one of several inner proxies, selected by a key.
+///
+/// Use this with a generated `router trait` to address one of N instances of a service:
+///
+/// ```rust,ignore
+/// let mut router = Router::new();
+/// router.insert(track_a, proxy_a);
+/// router.insert(track_b, proxy_b);
+/// router.increment_by(track_a, 2)?;
+/// ```
+pub struct Router<K, Msg> {
+    routes: HashMap<K, Box<dyn Proxy<Msg>>>,
+}
+
+impl<K, Msg> Router<K, Msg> {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl<K, Msg> Default for Router<K, Msg> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, Msg> Router<K, Msg> {
+    /// Register a proxy for `key`, returning the proxy previously registered for it, if any.
+    pub fn insert(
+        &mut self,
+        key: K,
+        proxy: impl Proxy<Msg> + 'static,
+    ) -> Option<Box<dyn Proxy<Msg>>> {
+        self.routes.insert(key, Box::new(proxy))
+    }
+
+    /// Remove the proxy registered for `key`
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Box<dyn Proxy<Msg>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.routes.remove(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.routes.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.routes.keys()
+    }
+}
+
+impl<K: Hash + Eq, Msg> RoutingProxy<K, Msg> for Router<K, Msg> {
+    type Error = UnknownRoute<K>;
+
+    fn send_to(&self, key: K, msg: Msg) -> core::result::Result<(), Self::Error> {
+        match self.routes.get(&key) {
+            Some(proxy) => {
+                proxy.send(msg);
+                Ok(())
+            }
+            None => Err(UnknownRoute(key)),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Support for plain threads, using only [std::sync::mpsc].
+use std::sync::mpsc;
+
+use super::FailedToSendRetval;
+use crate::Proxy;
+use crate::Returnval;
+use crate::StreamReturnval;
+
+/// A proxy that sends Msg through a [std::sync::mpsc] channel, either unbounded or bounded
+pub struct StdProxy<Msg> {
+    sender: StdSender<Msg>,
+}
+
+enum StdSender<Msg> {
+    Unbounded(mpsc::Sender<Msg>),
+    Bounded(mpsc::SyncSender<Msg>),
+}
+
+impl<Msg> StdProxy<Msg> {
+    pub fn new(sender: mpsc::Sender<Msg>) -> Self {
+        Self {
+            sender: StdSender::Unbounded(sender),
+        }
+    }
+
+    /// Create a proxy over a bounded channel. Sending blocks while the channel is full.
+    pub fn new_sync(sender: mpsc::SyncSender<Msg>) -> Self {
+        Self {
+            sender: StdSender::Bounded(sender),
+        }
+    }
+}
+
+impl<Msg> Clone for StdProxy<Msg> {
+    fn clone(&self) -> Self {
+        let sender = match &self.sender {
+            StdSender::Unbounded(tx) => StdSender::Unbounded(tx.clone()),
+            StdSender::Bounded(tx) => StdSender::Bounded(tx.clone()),
+        };
+        Self { sender }
+    }
+}
+
+impl<Msg> From<mpsc::Sender<Msg>> for StdProxy<Msg> {
+    fn from(sender: mpsc::Sender<Msg>) -> Self {
+        Self::new(sender)
+    }
+}
+
+impl<Msg> From<mpsc::SyncSender<Msg>> for StdProxy<Msg> {
+    fn from(sender: mpsc::SyncSender<Msg>) -> Self {
+        Self::new_sync(sender)
+    }
+}
+
+impl<Msg: core::fmt::Debug> Proxy<Msg> for StdProxy<Msg> {
+    fn send(&self, msg: Msg) {
+        match &self.sender {
+            StdSender::Unbounded(tx) => tx.send(msg).unwrap(),
+            StdSender::Bounded(tx) => tx.send(msg).unwrap(),
+        }
+    }
+}
+
+/// A `Send` return channel using [std::sync::mpsc].
+///
+/// The receiving end is a plain [mpsc::Receiver], so the caller can block on the result with
+/// [`recv`](mpsc::Receiver::recv) or [`recv_timeout`](mpsc::Receiver::recv_timeout), or poll it with
+/// [`try_recv`](mpsc::Receiver::try_recv).
+pub struct StdRetval;
+
+impl Returnval for StdRetval {
+    type Sender<T> = mpsc::SyncSender<T>;
+    type Receiver<T> = mpsc::Receiver<T>;
+    type SendError = FailedToSendRetval;
+
+    type RecvResult<T> = mpsc::Receiver<T>;
+
+    fn create<T>() -> (Self::Sender<T>, Self::Receiver<T>) {
+        mpsc::sync_channel(1)
+    }
+
+    fn recv<T>(rx: Self::Receiver<T>) -> Self::RecvResult<T> {
+        rx
+    }
+
+    fn send<T>(tx: Self::Sender<T>, msg: T) -> core::result::Result<(), Self::SendError> {
+        tx.send(msg).map_err(|_| FailedToSendRetval)
+    }
+}
+
+/// Streams are sent over an unbounded [mpsc::channel], and received as a plain [mpsc::Receiver],
+/// which can be iterated until the stream ends.
+impl StreamReturnval for StdRetval {
+    type StreamSender<T> = mpsc::Sender<T>;
+    type StreamReceiver<T> = mpsc::Receiver<T>;
+
+    type StreamRecvResult<T> = mpsc::Receiver<T>;
+
+    fn create_stream<T>() -> (Self::StreamSender<T>, Self::StreamReceiver<T>) {
+        mpsc::channel()
+    }
+
+    fn send_item<T>(
+        tx: &Self::StreamSender<T>,
+        item: T,
+    ) -> core::result::Result<(), Self::SendError> {
+        tx.send(item).map_err(|_| FailedToSendRetval)
+    }
+
+    fn recv_stream<T>(rx: Self::StreamReceiver<T>) -> Self::StreamRecvResult<T> {
+        rx
+    }
+}
@@ -0,0 +1,100 @@
+//! A timer thread shared by all promises, waking tasks at their deadlines.
+//!
+//! The thread is started by the first registered timer, and exits once no timers are left.
+use std::collections::BTreeMap;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::task::Waker;
+use std::time::Instant;
+
+struct Timers {
+    /// Keyed by deadline, and an id to tell timers with the same deadline apart
+    wakers: BTreeMap<(Instant, u64), Waker>,
+    next_id: u64,
+    running: bool,
+}
+
+static TIMERS: Mutex<Timers> = Mutex::new(Timers {
+    wakers: BTreeMap::new(),
+    next_id: 0,
+    running: false,
+});
+
+/// Notified when a timer is registered or removed
+static CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Timers> {
+    TIMERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Wakes the last registered waker at a deadline. Dropping the timer cancels it.
+pub(crate) struct Timer {
+    key: (Instant, u64),
+}
+
+impl Timer {
+    pub(crate) fn new(deadline: Instant) -> Self {
+        let mut timers = lock();
+        let id = timers.next_id;
+        timers.next_id += 1;
+        Self {
+            key: (deadline, id),
+        }
+    }
+
+    /// Wake `waker` at the deadline, instead of the waker registered before
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut timers = lock();
+        if let Some(registered) = timers.wakers.get_mut(&self.key) {
+            if !registered.will_wake(waker) {
+                *registered = waker.clone();
+            }
+            return;
+        }
+        timers.wakers.insert(self.key, waker.clone());
+        if !timers.running {
+            timers.running = true;
+            std::thread::spawn(run);
+        }
+        drop(timers);
+        CHANGED.notify_one();
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if lock().wakers.remove(&self.key).is_some() {
+            CHANGED.notify_one();
+        }
+    }
+}
+
+fn run() {
+    let mut timers = lock();
+    loop {
+        let now = Instant::now();
+        let pending = timers.wakers.split_off(&(now, u64::MAX));
+        let expired = std::mem::replace(&mut timers.wakers, pending);
+        if !expired.is_empty() {
+            // Woken tasks may register timers themselves
+            drop(timers);
+            expired.into_values().for_each(Waker::wake);
+            timers = lock();
+            continue;
+        }
+        timers = match timers.wakers.keys().next() {
+            Some(&(deadline, _)) => {
+                CHANGED
+                    .wait_timeout(timers, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => {
+                timers.running = false;
+                return;
+            }
+        };
+    }
+}
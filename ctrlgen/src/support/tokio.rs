@@ -1,6 +1,8 @@
 use super::promise;
+use super::promise::Spawner;
 use crate::Proxy;
 use crate::Returnval;
+use crate::StreamReturnval;
 
 use super::FailedToSendRetval;
 
@@ -40,4 +42,49 @@ impl Returnval for TokioRetval {
     fn send<T>(tx: Self::Sender<T>, msg: T) -> core::result::Result<(), Self::SendError> {
         tx.send(msg).map_err(|_| FailedToSendRetval)
     }
+
+    fn is_cancelled<T>(tx: &Self::Sender<T>) -> bool {
+        tx.is_cancelled()
+    }
+}
+
+/// Streams are sent over an unbounded tokio channel. The receiving end can be awaited item by item
+/// with [`recv`](tokio::sync::mpsc::UnboundedReceiver::recv), or polled with
+/// [`poll_recv`](tokio::sync::mpsc::UnboundedReceiver::poll_recv).
+impl StreamReturnval for TokioRetval {
+    type StreamSender<T> = tokio::sync::mpsc::UnboundedSender<T>;
+    type StreamReceiver<T> = tokio::sync::mpsc::UnboundedReceiver<T>;
+
+    type StreamRecvResult<T> = tokio::sync::mpsc::UnboundedReceiver<T>;
+
+    fn create_stream<T>() -> (Self::StreamSender<T>, Self::StreamReceiver<T>) {
+        tokio::sync::mpsc::unbounded_channel()
+    }
+
+    fn send_item<T>(
+        tx: &Self::StreamSender<T>,
+        item: T,
+    ) -> core::result::Result<(), Self::SendError> {
+        tx.send(item).map_err(|_| FailedToSendRetval)
+    }
+
+    fn recv_stream<T>(rx: Self::StreamReceiver<T>) -> Self::StreamRecvResult<T> {
+        rx
+    }
+
+    fn is_stream_cancelled<T>(tx: &Self::StreamSender<T>) -> bool {
+        tx.is_closed()
+    }
+}
+
+/// A [Spawner] that spawns futures on the current tokio runtime
+pub struct TokioSpawner;
+
+impl Spawner for TokioSpawner {
+    fn spawn<F>(&self, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(fut);
+    }
 }
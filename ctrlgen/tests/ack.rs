@@ -0,0 +1,103 @@
+use std::sync::mpsc;
+
+use ctrlgen::support::std::StdProxy;
+use ctrlgen::support::std::StdRetval;
+use ctrlgen::support::FnProxy;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Service {
+    flag: bool,
+    counter: i32,
+}
+
+#[ctrlgen::ctrlgen(
+    enum ServiceMsg,
+    trait ServiceProxy,
+    returnval = LocalRetval,
+)]
+impl Service {
+    #[ctrlgen_ack]
+    pub fn set_flag(&mut self, flag: bool) {
+        self.flag = flag;
+    }
+
+    pub fn increment(&mut self) {
+        self.counter += 1;
+    }
+}
+
+#[test]
+fn acknowledged_method() {
+    let service = std::cell::RefCell::new(Service::default());
+    let proxy = FnProxy::new(|msg: ServiceMsg| msg.call_mut(&mut service.borrow_mut()).unwrap());
+
+    let ack = proxy.set_flag(true);
+    assert_eq!(*ack.borrow(), Some(()));
+    // Methods without `ctrlgen_ack` are still fire-and-forget
+    let () = proxy.increment();
+    assert!(service.borrow().flag);
+}
+
+#[derive(Default)]
+struct Mixer {
+    volume: f32,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    enum MixerMsg,
+    trait MixerProxy,
+    returnval = StdRetval,
+    ack_all,
+)]
+impl Mixer {
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    pub fn volume(&mut self) -> f32 {
+        self.volume
+    }
+}
+
+#[test]
+fn ack_all() {
+    let (tx, rx) = mpsc::channel::<MixerMsg>();
+    let handle = std::thread::spawn(move || {
+        let mut mixer = Mixer::default();
+        for msg in rx {
+            msg.call_mut(&mut mixer).unwrap();
+        }
+    });
+    let proxy = StdProxy::new(tx);
+
+    assert_eq!(proxy.set_volume(0.5).recv(), Ok(()));
+    assert_eq!(proxy.volume().recv(), Ok(0.5));
+
+    drop(proxy);
+    handle.join().unwrap();
+}
+
+#[derive(Default)]
+struct Counter {
+    count: i32,
+}
+
+#[ctrlgen::ctrlgen(enum CounterMsg, trait CounterProxy, ack_all, returnval = LocalRetval)]
+impl Counter {
+    pub fn increment(&mut self) {
+        self.count += 1;
+    }
+}
+
+#[test]
+fn ack_all_before_returnval() {
+    let counter = std::cell::RefCell::new(Counter::default());
+    let proxy = FnProxy::new(|msg: CounterMsg| msg.call_mut(&mut counter.borrow_mut()).unwrap());
+
+    let ack = proxy.increment();
+    assert_eq!(*ack.borrow(), Some(()));
+    assert_eq!(counter.borrow().count, 1);
+}
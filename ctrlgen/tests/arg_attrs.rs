@@ -0,0 +1,84 @@
+use std::cell::RefCell;
+
+use ctrlgen::support::FnProxy;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Library {
+    loaded: Vec<(String, &'static str)>,
+    tokens: Vec<String>,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug, serde::Serialize)]
+    enum LibraryMsg,
+    trait LibraryProxy,
+)]
+impl Library {
+    pub fn load(
+        &mut self,
+        #[ctrlgen_to_owned] name: &str,
+        #[ctrlgen_enum_attr[serde(skip)]] category: &'static str,
+    ) {
+        self.loaded.push((name.to_owned(), category));
+    }
+
+    pub fn login(
+        &mut self,
+        #[ctrlgen_enum_attr[serde(rename = "user")]] name: String,
+        #[ctrlgen_enum_attr[serde(skip)]]
+        #[ctrlgen_to_owned]
+        token: &str,
+    ) {
+        let _ = name;
+        self.tokens.push(token.to_owned());
+    }
+}
+
+#[test]
+fn to_owned_only_applies_to_marked_arguments() {
+    let msg = LibraryMsg::Load {
+        name: String::from("kick.wav"),
+        category: "drums",
+    };
+    assert_eq!(
+        format!("{msg:?}"),
+        r#"Load { name: "kick.wav", category: "drums" }"#
+    );
+}
+
+#[test]
+fn serde_attributes_on_single_fields() {
+    let msg = LibraryMsg::Load {
+        name: String::from("kick.wav"),
+        category: "drums",
+    };
+    assert_eq!(
+        serde_json::to_string(&msg).unwrap(),
+        r#"{"Load":{"name":"kick.wav"}}"#
+    );
+
+    // The token is redacted when the message is serialized
+    let msg = LibraryMsg::Login {
+        name: String::from("admin"),
+        token: String::from("hunter2"),
+    };
+    assert_eq!(
+        serde_json::to_string(&msg).unwrap(),
+        r#"{"Login":{"user":"admin"}}"#
+    );
+}
+
+#[test]
+fn proxy_converts_borrowed_arguments() {
+    let library = RefCell::new(Library::default());
+    let proxy = FnProxy::new(|msg: LibraryMsg| msg.call_mut(&mut library.borrow_mut()).unwrap());
+
+    let name = String::from("snare.wav");
+    proxy.load(&name, "drums");
+    proxy.login("admin".into(), "hunter2");
+
+    let library = library.into_inner();
+    assert_eq!(library.loaded, [(String::from("snare.wav"), "drums")]);
+    assert_eq!(library.tokens, ["hunter2"]);
+}
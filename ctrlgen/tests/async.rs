@@ -1,5 +1,6 @@
-#![feature(impl_trait_in_assoc_type)]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
 
+#[cfg(feature = "tokio")]
 use ctrlgen::CallMutAsync;
 
 #[derive(Default)]
@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use ctrlgen::CallMutBatch;
+use ctrlgen::Returnval;
+
+/// Like `LocalRetval`, but sending fails if the receiver has been dropped
+struct WeakRetval;
+
+#[derive(Debug, PartialEq)]
+struct ReceiverDropped;
+
+impl Returnval for WeakRetval {
+    type Sender<T> = Weak<RefCell<Option<T>>>;
+    type Receiver<T> = Rc<RefCell<Option<T>>>;
+    type SendError = ReceiverDropped;
+    type RecvResult<T> = Self::Receiver<T>;
+
+    fn create<T>() -> (Self::Sender<T>, Self::Receiver<T>) {
+        let rx = Rc::new(RefCell::new(None));
+        (Rc::downgrade(&rx), rx)
+    }
+
+    fn send<T>(tx: Self::Sender<T>, msg: T) -> Result<(), Self::SendError> {
+        let rx = tx.upgrade().ok_or(ReceiverDropped)?;
+        rx.replace(Some(msg));
+        Ok(())
+    }
+
+    fn recv<T>(rx: Self::Receiver<T>) -> Self::RecvResult<T> {
+        rx
+    }
+}
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+    derived: i32,
+    hook_calls: u32,
+}
+
+#[ctrlgen::ctrlgen(enum ServiceMsg,
+    returnval = WeakRetval,
+)]
+impl Service {
+    pub fn increment_by(&mut self, arg: i32) -> i32 {
+        self.counter += arg;
+        self.counter
+    }
+
+    pub fn set_counter(&mut self, counter: i32) {
+        self.counter = counter;
+    }
+
+    #[ctrlgen_batch_hook]
+    fn recompute(&mut self) {
+        self.derived = self.counter * 2;
+        self.hook_calls += 1;
+    }
+}
+
+fn msgs() -> (Vec<ServiceMsg>, Rc<RefCell<Option<i32>>>) {
+    let kept = WeakRetval::create();
+    let dropped = WeakRetval::create();
+    let msgs = vec![
+        ServiceMsg::SetCounter { counter: 1 },
+        ServiceMsg::IncrementBy {
+            arg: 2,
+            ret: dropped.0,
+        },
+        ServiceMsg::IncrementBy {
+            arg: 3,
+            ret: kept.0,
+        },
+    ];
+    (msgs, kept.1)
+}
+
+#[test]
+fn batch_collects_errors_and_runs_hook_once() {
+    let mut service = Service::default();
+    let (msgs, ret) = msgs();
+
+    let errors = ServiceMsg::call_mut_batch(msgs, &mut service);
+
+    assert_eq!(errors, vec![(1, ReceiverDropped)]);
+    assert_eq!(*ret.borrow(), Some(6));
+    assert_eq!(service.counter, 6);
+    assert_eq!(service.derived, 12);
+    assert_eq!(service.hook_calls, 1);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_batch() {
+    use ctrlgen::CallMutAsyncBatch;
+
+    let mut service = Service::default();
+    let (msgs, ret) = msgs();
+
+    let errors = ServiceMsg::call_mut_async_batch(msgs, &mut service).await;
+
+    assert_eq!(errors, vec![(1, ReceiverDropped)]);
+    assert_eq!(*ret.borrow(), Some(6));
+    assert_eq!(service.derived, 12);
+    assert_eq!(service.hook_calls, 1);
+}
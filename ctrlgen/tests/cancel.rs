@@ -0,0 +1,115 @@
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::cell::RefCell;
+
+use ctrlgen::support::FnProxy;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+use ctrlgen::CallMutAsync;
+use ctrlgen::CancellationToken;
+use ctrlgen::Returnval;
+
+#[derive(Default)]
+struct Service {
+    calls: u32,
+    saw_cancelled: Option<bool>,
+}
+
+#[ctrlgen::ctrlgen(
+    enum ServiceMsg,
+    trait ServiceProxy,
+    returnval = LocalRetval,
+    skip_cancelled,
+)]
+impl Service {
+    pub fn expensive(&mut self, arg: i32) -> i32 {
+        self.calls += 1;
+        arg
+    }
+
+    pub fn unit(&mut self) {
+        self.calls += 1;
+    }
+}
+
+#[ctrlgen::ctrlgen(
+    enum TokenServiceMsg,
+    returnval = LocalRetval,
+    context(cancel: ctrlgen::CancellationToken),
+)]
+impl Service {
+    pub async fn scan(&mut self, cancel: CancellationToken<'_>, depth: u32) -> u32 {
+        self.calls += 1;
+        self.saw_cancelled = Some(cancel.is_cancelled());
+        depth
+    }
+}
+
+#[ctrlgen::ctrlgen(
+    enum ScaledServiceMsg,
+    returnval = LocalRetval,
+    context(scale: u32, cancel: ctrlgen::CancellationToken),
+)]
+impl Service {
+    pub fn scaled(&mut self, depth: u32, cancel: CancellationToken<'_>, scale: u32) -> u32 {
+        self.saw_cancelled = Some(cancel.is_cancelled());
+        depth * scale
+    }
+}
+
+#[test]
+fn local_retval_is_cancelled() {
+    let (tx, rx) = LocalRetval::create::<i32>();
+    assert!(!LocalRetval::is_cancelled(&tx));
+    drop(rx);
+    assert!(LocalRetval::is_cancelled(&tx));
+}
+
+#[test]
+fn skips_methods_without_receiver() {
+    let service = RefCell::new(Service::default());
+    let proxy = FnProxy::new(|msg: ServiceMsg| {
+        msg.call_mut(&mut *service.borrow_mut()).unwrap();
+    });
+
+    let ret = proxy.expensive(1);
+    assert_eq!(*ret.borrow(), Some(1));
+    proxy.unit();
+    assert_eq!(service.borrow().calls, 2);
+
+    let (tx, rx) = LocalRetval::create();
+    drop(rx);
+    let msg = ServiceMsg::Expensive { arg: 3, ret: tx };
+    msg.call_mut(&mut *service.borrow_mut()).unwrap();
+    assert_eq!(service.borrow().calls, 2);
+}
+
+#[test]
+fn cancellation_token() {
+    let mut service = Service::default();
+
+    let (tx, rx) = LocalRetval::create();
+    let msg = TokenServiceMsg::Scan { depth: 2, ret: tx };
+    futures_lite::future::block_on(msg.call_mut_async(&mut service)).unwrap();
+    assert_eq!(service.saw_cancelled, Some(false));
+    assert_eq!(*rx.borrow(), Some(2));
+
+    let (tx, rx) = LocalRetval::create();
+    drop(rx);
+    let msg = TokenServiceMsg::Scan { depth: 2, ret: tx };
+    futures_lite::future::block_on(msg.call_mut_async(&mut service)).unwrap();
+    assert_eq!(service.saw_cancelled, Some(true));
+    assert_eq!(service.calls, 2);
+}
+
+#[test]
+fn cancellation_token_with_other_context() {
+    let mut service = Service::default();
+
+    let (tx, rx) = LocalRetval::create();
+    let msg = ScaledServiceMsg::Scaled { depth: 2, ret: tx };
+    // The token is provided by the dispatch, so the context only holds `scale`
+    msg.call_mut_with_ctx(&mut service, 3).unwrap();
+    assert_eq!(service.saw_cancelled, Some(false));
+    assert_eq!(*rx.borrow(), Some(6));
+}
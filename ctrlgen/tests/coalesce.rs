@@ -0,0 +1,118 @@
+use ctrlgen::support::coalesce;
+use ctrlgen::CallMut;
+use ctrlgen::Coalesce;
+use ctrlgen::Proxy;
+
+#[derive(Default)]
+struct Service {
+    gain: f32,
+    params: [f32; 4],
+    triggers: u32,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug, PartialEq)]
+    pub enum ServiceMsg,
+    trait ServiceProxy,
+)]
+impl Service {
+    #[ctrlgen_coalesce]
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    #[ctrlgen_coalesce(param)]
+    pub fn set_param(&mut self, param: usize, value: f32) {
+        self.params[param] = value;
+    }
+
+    pub fn trigger(&mut self) {
+        self.triggers += 1;
+    }
+}
+
+#[test]
+fn coalesces_with() {
+    let gain = ServiceMsg::SetGain { gain: 1.0 };
+    assert!(gain.coalesces_with(&ServiceMsg::SetGain { gain: 2.0 }));
+    assert!(!gain.coalesces_with(&ServiceMsg::Trigger {}));
+
+    let param = ServiceMsg::SetParam {
+        param: 0,
+        value: 1.0,
+    };
+    assert!(param.coalesces_with(&ServiceMsg::SetParam {
+        param: 0,
+        value: 2.0
+    }));
+    assert!(!param.coalesces_with(&ServiceMsg::SetParam {
+        param: 1,
+        value: 2.0
+    }));
+
+    assert!(!ServiceMsg::Trigger {}.coalesces_with(&ServiceMsg::Trigger {}));
+}
+
+#[test]
+fn queue_replaces_pending_messages() {
+    let (proxy, rx) = coalesce::channel::<ServiceMsg>();
+
+    proxy.set_gain(0.1);
+    proxy.trigger();
+    proxy.set_param(0, 1.0);
+    proxy.set_param(1, 1.0);
+    for i in 0..100 {
+        proxy.set_gain(i as f32);
+        proxy.set_param(0, i as f32);
+    }
+    proxy.trigger();
+
+    let pending: Vec<_> = rx.drain().collect();
+    assert_eq!(
+        pending,
+        vec![
+            ServiceMsg::SetGain { gain: 99.0 },
+            ServiceMsg::Trigger {},
+            ServiceMsg::SetParam {
+                param: 0,
+                value: 99.0
+            },
+            ServiceMsg::SetParam {
+                param: 1,
+                value: 1.0
+            },
+            ServiceMsg::Trigger {},
+        ]
+    );
+
+    let mut service = Service::default();
+    proxy.set_gain(0.5);
+    proxy.send(ServiceMsg::SetGain { gain: 0.25 });
+    assert_eq!(rx.len(), 1);
+    drop(proxy);
+    while let Some(msg) = rx.recv() {
+        msg.call_mut(&mut service).unwrap();
+    }
+    assert_eq!(service.gain, 0.25);
+    assert_eq!(service.triggers, 0);
+}
+
+#[test]
+fn recv_across_threads() {
+    let (proxy, rx) = coalesce::channel::<ServiceMsg>();
+    let handle = std::thread::spawn(move || {
+        let mut service = Service::default();
+        while let Some(msg) = rx.recv() {
+            msg.call_mut(&mut service).unwrap();
+        }
+        service
+    });
+    for i in 0..1000 {
+        proxy.set_param(i % 4, i as f32);
+        proxy.trigger();
+    }
+    drop(proxy);
+    let service = handle.join().unwrap();
+    assert_eq!(service.params, [996.0, 997.0, 998.0, 999.0]);
+    assert_eq!(service.triggers, 1000);
+}
@@ -0,0 +1,90 @@
+#![cfg(all(feature = "concurrent", feature = "tokio"))]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use ctrlgen::support::concurrent;
+use ctrlgen::support::promise::Promise;
+use ctrlgen::support::tokio::TokioRetval;
+use ctrlgen::Returnval;
+use tokio::sync::mpsc;
+
+#[derive(Default)]
+struct Index {
+    entries: Vec<u32>,
+    running: Cell<usize>,
+    max_running: Cell<usize>,
+}
+
+#[ctrlgen::ctrlgen(enum IndexMsg, returnval = TokioRetval, concurrent)]
+impl Index {
+    async fn count(&self) -> usize {
+        self.running.set(self.running.get() + 1);
+        self.max_running
+            .set(self.max_running.get().max(self.running.get()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.running.set(self.running.get() - 1);
+        self.entries.len()
+    }
+
+    fn first(&self) -> Option<u32> {
+        self.entries.first().copied()
+    }
+
+    async fn insert(&mut self, entry: u32) {
+        assert_eq!(self.running.get(), 0, "`&mut self` method ran concurrently");
+        tokio::task::yield_now().await;
+        self.entries.push(entry);
+    }
+}
+
+fn count(tx: &mpsc::UnboundedSender<IndexMsg>) -> Promise<usize> {
+    let (ret, promise) = TokioRetval::create();
+    tx.send(IndexMsg::Count { ret }).unwrap();
+    promise
+}
+
+async fn serve(index: &mut Index, rx: mpsc::UnboundedReceiver<IndexMsg>, limit: usize) {
+    let mut rx = rx;
+    let messages = futures_lite::stream::poll_fn(|cx| rx.poll_recv(cx));
+    concurrent::serve(index, messages, limit).await.unwrap();
+}
+
+#[tokio::test]
+async fn shared_methods_run_concurrently() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let before = [count(&tx), count(&tx), count(&tx)];
+    tx.send(IndexMsg::Insert { entry: 7 }).unwrap();
+    let after = [count(&tx), count(&tx)];
+    let (ret, first) = TokioRetval::create();
+    tx.send(IndexMsg::First { ret }).unwrap();
+    drop(tx);
+
+    let mut index = Index::default();
+    serve(&mut index, rx, 2).await;
+
+    assert_eq!(index.max_running.get(), 2);
+    for promise in before {
+        assert_eq!(promise.await, Some(0));
+    }
+    for promise in after {
+        assert_eq!(promise.await, Some(1));
+    }
+    assert_eq!(first.await, Some(Some(7)));
+}
+
+#[tokio::test]
+async fn limit_of_one_runs_in_order() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let promises = [count(&tx), count(&tx), count(&tx)];
+    drop(tx);
+
+    let mut index = Index::default();
+    serve(&mut index, rx, 1).await;
+
+    assert_eq!(index.max_running.get(), 1);
+    for promise in promises {
+        assert_eq!(promise.await, Some(0));
+    }
+}
@@ -1,6 +1,3 @@
-use std::cell::RefCell;
-
-use ctrlgen::support::LocalRetval;
 use ctrlgen::CallMut;
 
 #[derive(Default)]
@@ -31,3 +28,53 @@ fn call_mut_works() {
 
     assert_eq!(service.last_ctx, 3)
 }
+
+#[derive(Default)]
+struct Voice {
+    gain: f32,
+    last_time: u64,
+}
+
+struct Clock {
+    time: u64,
+}
+
+#[ctrlgen::ctrlgen(
+    enum VoiceMsg,
+    context(buffer: &mut Vec<f32>, clock: &Clock),
+)]
+impl Voice {
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    pub fn render(&mut self, buffer: &mut Vec<f32>, frames: usize) {
+        buffer.extend(std::iter::repeat_n(self.gain, frames));
+    }
+
+    pub fn sync(&mut self, clock: &Clock, buffer: &mut Vec<f32>) {
+        self.last_time = clock.time;
+        buffer.clear();
+    }
+}
+
+#[test]
+fn borrowed_contexts() {
+    let mut voice = Voice::default();
+    let mut buffer = vec![];
+    let clock = Clock { time: 42 };
+
+    VoiceMsg::SetGain { gain: 0.5 }
+        .call_mut_with_ctx(&mut voice, (&mut buffer, &clock))
+        .unwrap();
+    VoiceMsg::Render { frames: 2 }
+        .call_mut_with_ctx(&mut voice, (&mut buffer, &clock))
+        .unwrap();
+    assert_eq!(buffer, [0.5, 0.5]);
+
+    VoiceMsg::Sync {}
+        .call_mut_with_ctx(&mut voice, (&mut buffer, &clock))
+        .unwrap();
+    assert!(buffer.is_empty());
+    assert_eq!(voice.last_time, 42);
+}
@@ -0,0 +1,42 @@
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+use ctrlgen::CallMutAsync;
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+}
+
+#[ctrlgen::ctrlgen(
+    enum ServiceMsg,
+    context(log: &mut Vec<String>),
+)]
+impl Service {
+    pub async fn increment_by(&mut self, arg: i32, log: &mut Vec<String>) {
+        self.counter += arg;
+        log.push(format!("counter is {}", self.counter));
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+}
+
+#[test]
+fn async_borrowed_context() {
+    let mut service = Service::default();
+    let mut log = vec![];
+
+    futures_lite::future::block_on(async {
+        ServiceMsg::IncrementBy { arg: 2 }
+            .call_mut_async_with_ctx(&mut service, &mut log)
+            .await
+            .unwrap();
+        ServiceMsg::Reset {}
+            .call_mut_async_with_ctx(&mut service, &mut log)
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(log, ["counter is 2"]);
+    assert_eq!(service.counter, 0);
+}
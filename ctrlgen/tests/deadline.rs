@@ -0,0 +1,98 @@
+use std::cell::RefCell;
+use std::time::Duration;
+use std::time::Instant;
+
+use ctrlgen::deadline::DeadlineExceeded;
+use ctrlgen::deadline::DeadlineExt;
+use ctrlgen::deadline::HasDeadline;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+use ctrlgen::Proxy;
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+}
+
+#[ctrlgen::ctrlgen(
+    enum ServiceMsg,
+    trait ServiceProxy,
+    returnval = LocalRetval,
+    deadline,
+)]
+impl Service {
+    pub fn increment_by(&mut self, arg: i32) -> i32 {
+        self.counter += arg;
+        self.counter
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+}
+
+/// Queues messages, to be handled later
+struct QueueProxy(RefCell<Vec<ServiceMsg>>);
+
+impl Proxy<ServiceMsg> for QueueProxy {
+    fn send(&self, msg: ServiceMsg) {
+        self.0.borrow_mut().push(msg)
+    }
+}
+
+impl QueueProxy {
+    fn run(&self, service: &mut Service) {
+        for msg in self.0.take() {
+            msg.call_mut(service).unwrap();
+        }
+    }
+}
+
+#[test]
+fn messages_without_deadline() {
+    let proxy = QueueProxy(Default::default());
+    let mut service = Service::default();
+
+    let ret = proxy.increment_by(2);
+    assert_eq!(proxy.0.borrow()[0].deadline(), None);
+    proxy.run(&mut service);
+    assert_eq!(*ret.borrow(), Some(Ok(2)));
+}
+
+#[test]
+fn expired_messages_are_rejected() {
+    let proxy = QueueProxy(Default::default());
+    let mut service = Service::default();
+
+    let in_time = proxy.with_timeout(Duration::from_secs(60)).increment_by(2);
+    let expired = proxy.with_deadline(Instant::now()).increment_by(3);
+    proxy.with_deadline(Instant::now()).reset();
+    std::thread::sleep(Duration::from_millis(1));
+    proxy.run(&mut service);
+
+    assert_eq!(*in_time.borrow(), Some(Ok(2)));
+    assert_eq!(*expired.borrow(), Some(Err(DeadlineExceeded)));
+    assert_eq!(service.counter, 2);
+}
+
+#[test]
+fn expired_unit_messages_are_dropped_silently() {
+    let proxy = QueueProxy(Default::default());
+    let mut service = Service { counter: 5 };
+
+    proxy.with_deadline(Instant::now()).reset();
+    std::thread::sleep(Duration::from_millis(1));
+    proxy.run(&mut service);
+    assert_eq!(service.counter, 5);
+}
+
+#[test]
+fn undispatched_messages_keep_waiting() {
+    let proxy = QueueProxy(Default::default());
+
+    let ret = proxy.with_deadline(Instant::now()).increment_by(2);
+    std::thread::sleep(Duration::from_millis(1));
+    // The deadline passed, but only dispatching the message resolves it
+    assert_eq!(*ret.borrow(), None);
+    assert_eq!(proxy.0.borrow().len(), 1);
+}
@@ -1,4 +1,4 @@
-#![feature(type_alias_impl_trait)]
+#![cfg_attr(feature = "nightly", feature(type_alias_impl_trait))]
 
 struct Service;
 
@@ -14,7 +14,7 @@ impl Service {
 #[test]
 fn enum_attr_word_syntax() {
     let msg = ServiceMsg::Foo {};
-    format!("{msg:?}");
+    let _ = format!("{msg:?}");
 }
 
 #[test]
@@ -22,3 +22,28 @@ fn enum_attr_hashtag_syntax() {
     let msg = ServiceMsg::Foo {};
     let _ = msg.clone();
 }
+
+struct Returning;
+
+#[ctrlgen::ctrlgen(
+    enum ReturningMsg,
+    returnval = ctrlgen::support::LocalRetval,
+)]
+impl Returning {
+    #[ctrlgen_return_attr[doc = "Receives the answer"]]
+    pub fn answer(&mut self) -> i32 {
+        42
+    }
+}
+
+#[test]
+fn return_attr() {
+    use ctrlgen::CallMut;
+    use ctrlgen::Returnval;
+
+    let (tx, rx) = ctrlgen::support::LocalRetval::create();
+    ReturningMsg::Answer { ret: tx }
+        .call_mut(&mut Returning)
+        .unwrap();
+    assert_eq!(*rx.borrow(), Some(42));
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "flume")]
+
+use ctrlgen::support::flume::FlumeProxy;
+use ctrlgen::support::flume::FlumeRetval;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+    name: String,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    pub enum ServiceMsg,
+    trait ServiceProxy,
+    async trait ServiceAsyncProxy,
+    returnval = FlumeRetval,
+)]
+impl Service {
+    pub fn increment_by(&mut self, arg: i32) -> i32 {
+        self.counter += arg;
+        self.counter
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+
+    pub fn rename(&mut self, #[ctrlgen_to_owned] name: &str) -> usize {
+        self.name = name.to_owned();
+        self.name.len()
+    }
+
+    pub fn set_name(&mut self, #[ctrlgen_to_owned] name: &str) {
+        self.name = name.to_owned();
+    }
+}
+
+fn spawn_service(rx: flume::Receiver<ServiceMsg>) -> std::thread::JoinHandle<Service> {
+    std::thread::spawn(move || {
+        let mut service = Service::default();
+        for msg in rx {
+            msg.call_mut(&mut service).unwrap();
+        }
+        service
+    })
+}
+
+#[test]
+fn blocking_request_response() {
+    let (tx, rx) = flume::unbounded();
+    let handle = spawn_service(rx);
+    let proxy = FlumeProxy::new(tx);
+
+    assert_eq!(ServiceProxy::increment_by(&proxy, 2).recv(), Ok(2));
+    ServiceProxy::reset(&proxy);
+    assert_eq!(ServiceProxy::increment_by(&proxy, 3).recv(), Ok(3));
+
+    drop(proxy);
+    assert_eq!(handle.join().unwrap().counter, 3);
+}
+
+#[test]
+fn async_request_response() {
+    let (tx, rx) = flume::bounded(1);
+    let handle = spawn_service(rx);
+    let proxy = FlumeProxy::new(tx);
+
+    futures_lite::future::block_on(async {
+        let ret = ServiceAsyncProxy::increment_by(&proxy, 2).await;
+        assert_eq!(ret.recv_async().await, Ok(2));
+        ServiceAsyncProxy::reset(&proxy).await;
+        let ret = ServiceAsyncProxy::increment_by(&proxy, 5).await;
+        assert_eq!(ret.recv_async().await, Ok(5));
+    });
+
+    drop(proxy);
+    assert_eq!(handle.join().unwrap().counter, 5);
+}
+
+#[test]
+fn async_proxy_with_reference_arguments() {
+    let (tx, rx) = flume::bounded(1);
+    let handle = spawn_service(rx);
+    let proxy = FlumeProxy::new(tx);
+
+    futures_lite::future::block_on(async {
+        let name = String::from("Drums");
+        let ret = ServiceAsyncProxy::rename(&proxy, &name);
+        drop(name);
+        assert_eq!(ret.await.recv_async().await, Ok(5));
+        ServiceAsyncProxy::set_name(&proxy, &String::from("Bass")).await;
+    });
+
+    drop(proxy);
+    assert_eq!(handle.join().unwrap().name, "Bass");
+}
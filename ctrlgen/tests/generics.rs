@@ -1,4 +1,4 @@
-#![feature(type_alias_impl_trait)]
+#![cfg_attr(feature = "nightly", feature(type_alias_impl_trait))]
 use std::cell::RefCell;
 
 use ctrlgen::support::FnProxy;
@@ -26,7 +26,7 @@ fn proxy() {
     let service = RefCell::new(Service { counter: 0 });
 
     // With proxy:
-    let proxy = FnProxy::new(|msg: ServiceMsg| {
+    let proxy = FnProxy::new(|msg: ServiceMsg<i32>| {
         msg.call_mut(&mut *service.borrow_mut()).unwrap();
     });
 
@@ -34,3 +34,95 @@ fn proxy() {
     assert_eq!(*ret.borrow(), Some(2));
     assert_eq!(service.borrow().counter, 2);
 }
+
+trait Sample: Copy + Default + std::ops::Add<Output = Self> {}
+impl Sample for f32 {}
+
+#[derive(Default)]
+struct Engine<T: Sample> {
+    value: T,
+}
+
+#[ctrlgen::ctrlgen(pub enum EngineMsg,
+    returnval = LocalRetval,
+    trait EngineProxy,
+)]
+impl<T: Sample> Engine<T> {
+    pub fn set_value(&mut self, v: T) {
+        self.value = v;
+    }
+
+    pub fn add(&mut self, v: T) -> T {
+        self.value = self.value + v;
+        self.value
+    }
+}
+
+#[test]
+fn generic_arguments() {
+    let engine = RefCell::new(Engine::<f32>::default());
+    let proxy = FnProxy::new(|msg: EngineMsg<f32>| {
+        msg.call_mut(&mut *engine.borrow_mut()).unwrap();
+    });
+
+    proxy.set_value(1.5);
+    let ret = proxy.add(1.0);
+    assert_eq!(*ret.borrow(), Some(2.5));
+}
+
+struct Meter<T> {
+    count: usize,
+    _sample: std::marker::PhantomData<T>,
+}
+
+// `T` is not used by any message
+#[ctrlgen::ctrlgen(enum MeterMsg)]
+impl<T> Meter<T> {
+    pub fn tick(&mut self) {
+        self.count += 1;
+    }
+}
+
+#[test]
+fn unused_parameters() {
+    let mut meter = Meter::<u8> {
+        count: 0,
+        _sample: std::marker::PhantomData,
+    };
+    MeterMsg::Tick {}.call_mut(&mut meter).unwrap();
+    assert_eq!(meter.count, 1);
+}
+
+struct Mixer<T> {
+    gain: f32,
+    _sample: std::marker::PhantomData<T>,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(serde::Serialize, serde::Deserialize)]
+    enum MixerMsg,
+)]
+impl<T> Mixer<T> {
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+}
+
+/// Not serializable, and not used by any message
+struct Voice;
+
+#[test]
+fn serde_derives_skip_unused_parameters() {
+    let msg = MixerMsg::<Voice>::SetGain { gain: 0.5 };
+    let json = serde_json::to_string(&msg).unwrap();
+    assert_eq!(json, r#"{"SetGain":{"gain":0.5}}"#);
+    let parsed: MixerMsg<Voice> = serde_json::from_str(&json).unwrap();
+    assert!(matches!(parsed, MixerMsg::SetGain { gain } if gain == 0.5));
+
+    let mut mixer = Mixer::<Voice> {
+        gain: 1.0,
+        _sample: std::marker::PhantomData,
+    };
+    parsed.call_mut(&mut mixer).unwrap();
+    assert_eq!(mixer.gain, 0.5);
+}
@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::path::PathBuf;
+
+use ctrlgen::support::FnProxy;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Sampler {
+    name: String,
+    path: PathBuf,
+    tags: Vec<String>,
+    volume: f64,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    enum SamplerMsg,
+    trait SamplerProxy,
+)]
+impl Sampler {
+    pub fn rename(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    pub fn load(&mut self, path: impl AsRef<Path>) {
+        self.path = path.as_ref().to_owned();
+    }
+
+    pub fn tag(&mut self, #[ctrlgen_into(Box<str>)] tag: impl AsRef<str>) {
+        self.tags.push(tag.as_ref().to_owned());
+    }
+
+    pub fn set_volume(&mut self, volume: impl Into<f64>) {
+        self.volume = volume.into();
+    }
+
+    pub fn set_label(&mut self, #[ctrlgen_into(Box<str>)] label: impl Into<String>) {
+        self.tags = vec![label.into()];
+    }
+
+    pub fn set_peak(&mut self, #[ctrlgen_into(AnySample)] peak: impl Sample) {
+        self.volume = peak.level();
+    }
+}
+
+trait Sample {
+    fn level(&self) -> f64;
+}
+
+impl Sample for f32 {
+    fn level(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Sample for i16 {
+    fn level(&self) -> f64 {
+        *self as f64 / i16::MAX as f64
+    }
+}
+
+#[derive(Debug)]
+struct AnySample(f64);
+
+impl From<i16> for AnySample {
+    fn from(sample: i16) -> Self {
+        Self(sample.level())
+    }
+}
+
+impl Sample for AnySample {
+    fn level(&self) -> f64 {
+        self.0
+    }
+}
+
+#[test]
+fn message_stores_concrete_types() {
+    let msg = SamplerMsg::Rename {
+        name: String::from("Kick"),
+    };
+    assert_eq!(format!("{msg:?}"), r#"Rename { name: "Kick" }"#);
+    let msg = SamplerMsg::Load {
+        path: PathBuf::from("kick.wav"),
+    };
+    assert_eq!(format!("{msg:?}"), r#"Load { path: "kick.wav" }"#);
+    let msg = SamplerMsg::Tag {
+        tag: Box::from("drums"),
+    };
+    assert_eq!(format!("{msg:?}"), r#"Tag { tag: "drums" }"#);
+}
+
+#[test]
+fn proxy_converts_at_call_site() {
+    let sampler = RefCell::new(Sampler::default());
+    let proxy = FnProxy::new(|msg: SamplerMsg| msg.call_mut(&mut sampler.borrow_mut()).unwrap());
+
+    proxy.rename("Snare");
+    proxy.load(Path::new("samples/snare.wav"));
+    proxy.set_volume(0.5f32);
+
+    let sampler = sampler.into_inner();
+    assert_eq!(sampler.name, "Snare");
+    assert_eq!(sampler.path, Path::new("samples/snare.wav"));
+    assert_eq!(sampler.volume, 0.5);
+}
+
+#[test]
+fn explicit_message_types() {
+    let mut sampler = Sampler::default();
+    let sent = RefCell::new(Vec::new());
+    let proxy = FnProxy::new(|msg: SamplerMsg| sent.borrow_mut().push(msg));
+
+    proxy.set_label("drums");
+    proxy.tag(String::from("acoustic"));
+    proxy.tag("dry");
+    proxy.set_peak(i16::MAX);
+
+    for msg in sent.into_inner() {
+        msg.call_mut(&mut sampler).unwrap();
+    }
+    assert_eq!(sampler.tags, ["drums", "acoustic", "dry"]);
+    assert_eq!(sampler.volume, 1.0);
+}
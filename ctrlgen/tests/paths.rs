@@ -0,0 +1,67 @@
+use ctrlgen::CallMut;
+
+mod engine {
+    pub mod voice {
+        #[derive(Default)]
+        pub struct Voice {
+            pub gain: f32,
+        }
+
+        #[derive(Default)]
+        pub struct Bank<T> {
+            pub items: Vec<T>,
+        }
+    }
+}
+
+mod control {
+    use std::cell::RefCell;
+
+    use ctrlgen::support::FnProxy;
+    use ctrlgen::CallMut;
+
+    #[ctrlgen::ctrlgen(pub enum VoiceMsg, trait VoiceProxy)]
+    impl crate::engine::voice::Voice {
+        pub fn set_gain(&mut self, gain: f32) {
+            self.gain = gain;
+        }
+
+        pub fn copy_from(&mut self, other: Self) {
+            self.gain = other.gain;
+        }
+    }
+
+    #[ctrlgen::ctrlgen(pub enum BankMsg, trait BankProxy)]
+    impl<T> super::engine::voice::Bank<T> {
+        pub fn push(&mut self, item: T) {
+            self.items.push(item);
+        }
+    }
+
+    #[test]
+    fn proxy_in_the_macro_module() {
+        let voice = RefCell::new(crate::engine::voice::Voice::default());
+        let proxy = FnProxy::new(|msg: VoiceMsg| msg.call_mut(&mut voice.borrow_mut()).unwrap());
+        proxy.set_gain(0.5);
+        proxy.copy_from(crate::engine::voice::Voice { gain: 0.25 });
+        assert_eq!(voice.into_inner().gain, 0.25);
+    }
+}
+
+#[test]
+fn service_implemented_from_another_module() {
+    let mut voice = engine::voice::Voice::default();
+    control::VoiceMsg::SetGain { gain: 0.75 }
+        .call_mut(&mut voice)
+        .unwrap();
+    assert_eq!(voice.gain, 0.75);
+}
+
+#[test]
+fn generic_service_path() {
+    let mut bank = engine::voice::Bank::default();
+    control::BankMsg::Push { item: "kick" }
+        .call_mut(&mut bank)
+        .unwrap();
+    assert_eq!(bank.items, ["kick"]);
+}
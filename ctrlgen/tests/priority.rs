@@ -0,0 +1,103 @@
+use ctrlgen::support::priority;
+use ctrlgen::CallMut;
+use ctrlgen::Prioritized;
+use ctrlgen::Priority;
+
+#[derive(Default)]
+struct Service {
+    log: Vec<&'static str>,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug, PartialEq)]
+    pub enum ServiceMsg,
+    trait ServiceProxy,
+)]
+impl Service {
+    #[ctrlgen_priority(high)]
+    pub fn stop(&mut self) {
+        self.log.push("stop");
+    }
+
+    pub fn set_param(&mut self, value: f32) {
+        let _ = value;
+        self.log.push("set_param");
+    }
+
+    #[ctrlgen_priority(low)]
+    pub fn gc(&mut self) {
+        self.log.push("gc");
+    }
+}
+
+#[test]
+fn generated_priority() {
+    assert_eq!(ServiceMsg::Stop {}.priority(), Priority::High);
+    assert_eq!(
+        ServiceMsg::SetParam { value: 1.0 }.priority(),
+        Priority::Normal
+    );
+    assert_eq!(ServiceMsg::Gc {}.priority(), Priority::Low);
+}
+
+#[test]
+fn higher_lanes_drain_first() {
+    let (proxy, rx) = priority::channel::<ServiceMsg>();
+
+    proxy.gc();
+    for i in 0..3 {
+        proxy.set_param(i as f32);
+    }
+    proxy.stop();
+
+    assert_eq!(rx.len(), 5);
+    assert_eq!(rx.try_recv(), Some(ServiceMsg::Stop {}));
+    assert_eq!(rx.try_recv(), Some(ServiceMsg::SetParam { value: 0.0 }));
+    proxy.stop();
+    assert_eq!(rx.try_recv(), Some(ServiceMsg::Stop {}));
+
+    let mut service = Service::default();
+    drop(proxy);
+    while let Some(msg) = rx.recv() {
+        msg.call_mut(&mut service).unwrap();
+    }
+    assert_eq!(service.log, vec!["set_param", "set_param", "gc"]);
+}
+
+#[test]
+fn drain_in_priority_order() {
+    let (proxy, rx) = priority::channel::<ServiceMsg>();
+    proxy.gc();
+    proxy.set_param(1.0);
+    proxy.stop();
+
+    let pending: Vec<_> = rx.drain().collect();
+    assert_eq!(
+        pending,
+        vec![
+            ServiceMsg::Stop {},
+            ServiceMsg::SetParam { value: 1.0 },
+            ServiceMsg::Gc {}
+        ]
+    );
+    assert!(rx.is_empty());
+}
+
+struct Plain;
+
+#[ctrlgen::ctrlgen(enum PlainMsg, trait PlainProxy)]
+impl Plain {
+    pub fn first(&mut self) {}
+    pub fn second(&mut self) {}
+}
+
+#[test]
+fn default_priority_without_attributes() {
+    assert_eq!(PlainMsg::First {}.priority(), Priority::Normal);
+
+    let (proxy, rx) = priority::channel::<PlainMsg>();
+    proxy.second();
+    proxy.first();
+    assert!(matches!(rx.try_recv(), Some(PlainMsg::Second {})));
+    assert!(matches!(rx.try_recv(), Some(PlainMsg::First {})));
+}
@@ -0,0 +1,396 @@
+#![cfg(feature = "promise")]
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::RawWaker;
+use std::task::RawWakerVTable;
+use std::task::Waker;
+use std::time::Duration;
+
+use ctrlgen::support::oneshot;
+use ctrlgen::support::promise::Promise;
+use ctrlgen::support::promise::ThreadSpawner;
+use futures_lite::future::block_on;
+use futures_lite::future::poll_once;
+
+#[test]
+fn channel_resolves() {
+    let (tx, promise) = Promise::channel();
+    assert!(!promise.is_ready());
+    assert_eq!(promise.get(), None);
+    tx.send(42).unwrap();
+    assert!(promise.is_ready());
+    assert_eq!(promise.get(), Some(&42));
+    assert_eq!(promise.block_on(), Some(42));
+}
+
+#[test]
+fn dropped_sender_is_empty() {
+    let (tx, promise) = Promise::<i32>::channel();
+    drop(tx);
+    assert!(promise.is_empty());
+    assert_eq!(promise.block_on(), None);
+}
+
+#[test]
+fn spawn_without_runtime() {
+    let promise = Promise::spawn_with(&ThreadSpawner, async { 1 + 1 });
+    let mapped = promise.map_promise_with(&ThreadSpawner, |x| x * 10);
+    let chained = mapped.then_promise_with(&ThreadSpawner, |x| async move { x + 1 });
+    assert_eq!(chained.block_on(), Some(21));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn spawn_on_tokio() {
+    let promise = Promise::spawn(async { 2 }).map_promise(|x| x * 2);
+    assert_eq!(promise.await, Some(4));
+}
+
+#[test]
+fn join_all_polls_through_get() {
+    let (tx1, p1) = Promise::channel();
+    let (tx2, p2) = Promise::channel();
+    let joined = Promise::join_all(vec![p1, p2, Promise::ready(3)]);
+
+    assert_eq!(joined.get(), None);
+    tx2.send(2).unwrap();
+    assert_eq!(joined.get(), None);
+    tx1.send(1).unwrap();
+    assert_eq!(joined.get(), Some(&vec![1, 2, 3]));
+}
+
+#[test]
+fn join_all_with_empty_promise_is_empty() {
+    let (_tx, pending) = Promise::<i32>::channel();
+    let joined = Promise::join_all(vec![pending, Promise::empty()]);
+    assert!(joined.is_empty());
+}
+
+#[test]
+fn select_resolves_first() {
+    let (tx1, p1) = Promise::channel();
+    let (tx2, p2) = Promise::channel();
+    let selected = Promise::select(vec![p1, p2]);
+
+    assert_eq!(selected.get(), None);
+    tx2.send("second").unwrap();
+    assert_eq!(selected.get(), Some(&"second"));
+    // The other promises are dropped once one has resolved
+    assert!(tx1.send("first").is_err());
+    assert_eq!(selected.block_on(), Some("second"));
+
+    let (tx, p) = Promise::<i32>::channel();
+    let selected = Promise::select(vec![p, Promise::empty()]);
+    assert!(!selected.is_empty());
+    drop(tx);
+    assert!(selected.is_empty());
+}
+
+#[test]
+fn with_timeout() {
+    let (_tx, pending) = Promise::<i32>::channel();
+    let timed_out = pending.with_timeout(Duration::from_millis(10));
+    assert_eq!(timed_out.get(), None);
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(timed_out.is_empty());
+
+    let (_tx, pending) = Promise::<i32>::channel();
+    assert_eq!(
+        pending.with_timeout(Duration::from_millis(10)).block_on(),
+        None
+    );
+
+    let (tx, pending) = Promise::channel();
+    let resolved = pending.with_timeout(Duration::from_secs(60));
+    tx.send(5).unwrap();
+    assert_eq!(resolved.block_on(), Some(5));
+}
+
+#[test]
+fn with_timeout_wakes_each_awaiting_task() {
+    let (_tx, pending) = Promise::<i32>::channel();
+    // Cancelled before its deadline, and must not hold up the others
+    drop(pending.with_timeout(Duration::from_millis(5)));
+    let waiters: Vec<_> = [30, 10, 20]
+        .into_iter()
+        .map(|ms| {
+            let (_tx, pending) = Promise::<i32>::channel();
+            std::thread::spawn(move || pending.with_timeout(Duration::from_millis(ms)).block_on())
+        })
+        .collect();
+    for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), None);
+    }
+}
+
+#[test]
+fn or_default() {
+    assert_eq!(Promise::<i32>::empty().or_default().get(), Some(&0));
+    assert_eq!(Promise::ready(3).or_default().get(), Some(&3));
+
+    let (tx, pending) = Promise::<i32>::channel();
+    let defaulted = pending.or_default();
+    assert!(!defaulted.is_ready());
+    drop(tx);
+    assert_eq!(defaulted.get(), Some(&0));
+}
+
+#[test]
+fn dropped_promise_cancels_sender() {
+    let (tx, promise) = Promise::<i32>::channel();
+    assert!(!tx.is_cancelled());
+    drop(promise);
+    assert!(tx.is_cancelled());
+    assert_eq!(tx.send(1), Err(1));
+}
+
+/// Counts how often it was dropped, to catch values dropped twice or leaked
+#[derive(Debug)]
+struct Tracked(Arc<AtomicUsize>);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn get_stays_valid_while_checking() {
+    let (tx, promise) = Promise::channel();
+    tx.send(String::from("value")).unwrap();
+    let value = promise.get().unwrap();
+    assert!(promise.is_ready());
+    assert!(!promise.is_empty());
+    assert_eq!(promise.get(), Some(value));
+    assert_eq!(value, "value");
+}
+
+#[test]
+fn take_leaves_empty() {
+    let mut promise = Promise::ready(String::from("value"));
+    assert_eq!(promise.take().as_deref(), Some("value"));
+    assert!(promise.is_empty());
+    assert_eq!(promise.get(), None);
+    assert_eq!(promise.take(), None);
+
+    let (tx, mut promise) = Promise::channel();
+    assert_eq!(promise.take(), None);
+    assert!(!promise.is_empty());
+    tx.send(1).unwrap();
+    assert_eq!(promise.take(), Some(1));
+    assert!(promise.is_empty());
+}
+
+#[test]
+fn polling_after_completion_is_empty() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (tx, mut promise) = Promise::channel();
+    assert!(block_on(poll_once(&mut promise)).is_none());
+    tx.send(Tracked(drops.clone())).unwrap();
+
+    let value = block_on(poll_once(&mut promise)).unwrap();
+    assert!(value.is_some());
+    assert!(matches!(block_on(poll_once(&mut promise)), Some(None)));
+    assert!(promise.is_empty());
+    drop(promise);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    drop(value);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn values_are_dropped_once() {
+    let drops = Arc::new(AtomicUsize::new(0));
+
+    let (tx, promise) = Promise::channel();
+    tx.send(Tracked(drops.clone())).unwrap();
+    assert!(promise.get().is_some());
+    drop(promise);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    // Received by the channel, but never checked
+    let (tx, promise) = Promise::channel();
+    tx.send(Tracked(drops.clone())).unwrap();
+    drop(promise);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+
+    let promise = Promise::ready(Tracked(drops.clone()));
+    drop(block_on(promise));
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn deferred_resolves_through_get_and_await() {
+    let (tx, pending) = Promise::channel();
+    let mut defaulted = pending.or_default();
+    assert_eq!(defaulted.get(), None);
+    assert!(block_on(poll_once(&mut defaulted)).is_none());
+    tx.send(String::from("value")).unwrap();
+    assert_eq!(defaulted.get().map(String::as_str), Some("value"));
+    assert_eq!(block_on(defaulted).as_deref(), Some("value"));
+
+    let (tx, pending) = Promise::<String>::channel();
+    let joined = Promise::join_all(vec![pending]);
+    drop(tx);
+    assert!(joined.is_empty());
+    assert_eq!(block_on(joined), None);
+}
+
+#[test]
+fn debug_shows_state() {
+    let (tx, promise) = Promise::channel();
+    assert_eq!(format!("{promise:?}"), "Pending");
+    tx.send(1).unwrap();
+    assert!(promise.is_ready());
+    assert_eq!(format!("{promise:?}"), "Ready(1)");
+    assert_eq!(format!("{:?}", Promise::<i32>::empty()), "Empty");
+}
+
+fn counter() -> (Arc<AtomicUsize>, impl Fn() + Send + Sync + 'static) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let notified = count.clone();
+    (count, move || {
+        notified.fetch_add(1, Ordering::SeqCst);
+    })
+}
+
+#[test]
+fn notify_fires_when_value_arrives() {
+    let (count, notify) = counter();
+    let (tx, mut promise) = Promise::channel();
+    promise.set_notify(notify);
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(promise.get(), None);
+
+    std::thread::spawn(move || tx.send(3).unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(promise.get(), Some(&3));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn notify_fires_when_value_arrives_during_get() {
+    let rounds = if cfg!(miri) { 10 } else { 200 };
+    for _ in 0..rounds {
+        let (count, notify) = counter();
+        let (tx, mut promise) = Promise::channel();
+        promise.set_notify(notify);
+        let sender = std::thread::spawn(move || tx.send(3).unwrap());
+        while promise.get().is_none() {
+            std::hint::spin_loop();
+        }
+        sender.join().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[test]
+fn notify_fires_when_sender_is_dropped() {
+    let (count, notify) = counter();
+    let (tx, mut promise) = Promise::<i32>::channel();
+    promise.set_notify(notify);
+    drop(tx);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(promise.is_empty());
+}
+
+#[test]
+fn notify_fires_right_away_when_resolved() {
+    let (count, notify) = counter();
+    let mut promise = Promise::ready(1);
+    promise.set_notify(notify);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    // Resolved, but not yet checked by the promise
+    let (count, notify) = counter();
+    let (tx, mut promise) = Promise::channel();
+    tx.send(1).unwrap();
+    promise.set_notify(notify);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(promise.get(), Some(&1));
+}
+
+#[test]
+fn replaced_notify_is_not_called() {
+    let (first, notify) = counter();
+    let (tx, mut promise) = Promise::channel();
+    promise.set_notify(notify);
+    let (second, notify) = counter();
+    promise.set_notify(notify);
+
+    tx.send(1).unwrap();
+    assert_eq!(first.load(Ordering::SeqCst), 0);
+    assert_eq!(second.load(Ordering::SeqCst), 1);
+    assert_eq!(promise.block_on(), Some(1));
+}
+
+#[test]
+fn on_ready_waits_for_combinators() {
+    let (count, notify) = counter();
+    let (tx1, p1) = Promise::channel();
+    let (tx2, p2) = Promise::channel();
+    let joined = Promise::join_all(vec![p1, p2]).on_ready(notify);
+
+    tx1.send(1).unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    tx2.send(2).unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(joined.get(), Some(&vec![1, 2]));
+}
+
+#[test]
+fn on_ready_fires_on_timeout() {
+    let (count, notify) = counter();
+    let (_tx, pending) = Promise::<i32>::channel();
+    let timed_out = pending
+        .with_timeout(Duration::from_millis(10))
+        .on_ready(notify);
+    while count.load(Ordering::SeqCst) == 0 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(timed_out.is_empty());
+}
+
+#[test]
+fn notified_promise_can_be_awaited() {
+    let (count, notify) = counter();
+    let (tx, promise) = Promise::channel();
+    let promise = promise.on_ready(notify);
+    let sender = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        tx.send(String::from("value")).unwrap();
+    });
+    assert_eq!(promise.block_on().as_deref(), Some("value"));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    sender.join().unwrap();
+}
+
+#[test]
+fn oneshot_survives_a_poisoned_lock() {
+    // A waker that panics when cloned, which the receiver does while its lock is held
+    fn panicking_clone(_: *const ()) -> RawWaker {
+        panic!("waker cloned")
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(panicking_clone, noop, noop, noop);
+    let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+
+    let (tx, mut rx) = oneshot::channel();
+    let poll = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = Pin::new(&mut rx).poll(&mut Context::from_waker(&waker));
+    }));
+    assert!(poll.is_err());
+
+    assert!(!tx.is_cancelled());
+    tx.send(1).unwrap();
+    assert_eq!(rx.try_recv(), Ok(1));
+    // Dropping the ends doesn't panic on the poisoned lock
+    drop(rx);
+}
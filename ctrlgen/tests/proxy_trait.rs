@@ -1,4 +1,4 @@
-#![feature(type_alias_impl_trait)]
+#![cfg_attr(feature = "nightly", feature(type_alias_impl_trait))]
 use std::cell::RefCell;
 
 use ctrlgen::support::LocalRetval;
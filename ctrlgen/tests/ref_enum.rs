@@ -0,0 +1,119 @@
+use ctrlgen::support::DirectProxy;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Mixer {
+    name: String,
+    gain: f32,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    pub enum MixerMsg,
+    #[derive(Debug)]
+    ref enum MixerMsgRef,
+    trait MixerProxy,
+    ref trait MixerRefProxy,
+    returnval = LocalRetval,
+)]
+impl Mixer {
+    pub fn set_name(&mut self, #[ctrlgen_to_owned] name: &str) {
+        self.name = name.to_owned();
+    }
+
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    pub fn rename(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    pub fn process(&mut self, #[ctrlgen_to_owned] samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * self.gain).sum()
+    }
+}
+
+#[test]
+fn borrowed_messages_call_the_service() {
+    let mut mixer = Mixer::default();
+    let name = String::from("Drums");
+    MixerMsgRef::SetName { name: &name }
+        .call_mut(&mut mixer)
+        .unwrap();
+    MixerMsgRef::SetGain { gain: 0.5 }
+        .call_mut(&mut mixer)
+        .unwrap();
+    assert_eq!(mixer.name, "Drums");
+    assert_eq!(mixer.gain, 0.5);
+}
+
+#[test]
+fn ref_proxy_dispatches_without_copying() {
+    let proxy = DirectProxy::new(Mixer {
+        gain: 2.0,
+        ..Default::default()
+    });
+    let samples = vec![0.25, 0.5, 1.0];
+    let sum = MixerRefProxy::process(&proxy, &samples);
+    assert_eq!(*sum.borrow(), Some(3.5));
+
+    MixerRefProxy::set_name(&proxy, "Bass");
+    assert_eq!(proxy.into_inner().name, "Bass");
+}
+
+#[test]
+fn ref_proxy_converts_impl_arguments() {
+    let proxy = DirectProxy::new(Mixer::default());
+    MixerRefProxy::rename(&proxy, "Pads");
+    assert_eq!(proxy.into_inner().name, "Pads");
+}
+
+#[test]
+fn borrowed_messages_convert_to_owned() {
+    let mut queue: Vec<MixerMsg> = Vec::new();
+    {
+        let name = String::from("Keys");
+        let msg = MixerMsgRef::SetName { name: &name };
+        assert_eq!(format!("{msg:?}"), r#"SetName { name: "Keys" }"#);
+        queue.push(msg.into());
+        queue.push(MixerMsgRef::SetGain { gain: 0.75 }.into_owned());
+    }
+    assert_eq!(format!("{:?}", queue[0]), r#"SetName { name: "Keys" }"#);
+
+    let mut mixer = Mixer::default();
+    for msg in queue {
+        msg.call_mut(&mut mixer).unwrap();
+    }
+    assert_eq!(mixer.name, "Keys");
+    assert_eq!(mixer.gain, 0.75);
+}
+
+#[test]
+fn owned_proxy_still_works() {
+    let proxy = DirectProxy::new(Mixer::default());
+    MixerProxy::set_gain(&proxy, 1.5);
+    MixerProxy::set_name(&proxy, "Lead");
+    let mixer = proxy.into_inner();
+    assert_eq!(mixer.gain, 1.5);
+    assert_eq!(mixer.name, "Lead");
+}
+
+struct Counter<T> {
+    count: T,
+}
+
+#[ctrlgen::ctrlgen(enum CounterMsg, ref enum CounterMsgRef, ref trait CounterRefProxy)]
+impl<T: core::ops::AddAssign + Copy> Counter<T> {
+    fn add(&mut self, amount: T) {
+        self.count += amount;
+    }
+}
+
+#[test]
+fn ref_enum_without_borrowed_arguments() {
+    let proxy = DirectProxy::new(Counter { count: 1u8 });
+    proxy.add(2);
+    assert_eq!(proxy.into_inner().count, 3);
+}
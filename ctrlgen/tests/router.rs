@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ctrlgen::support::router::Router;
+use ctrlgen::support::router::UnknownRoute;
+use ctrlgen::support::FnProxy;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+}
+
+#[ctrlgen::ctrlgen(pub enum ServiceMsg,
+    returnval = LocalRetval,
+    proxy(trait ServiceProxy; router trait ServiceRouter),
+)]
+impl Service {
+    pub fn increment_by(&mut self, arg: i32) -> i32 {
+        self.counter += arg;
+        self.counter
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+}
+
+fn service_proxy(service: &Rc<RefCell<Service>>) -> FnProxy<ServiceMsg, impl Fn(ServiceMsg)> {
+    let service = service.clone();
+    FnProxy::new(move |msg: ServiceMsg| {
+        msg.call_mut(&mut *service.borrow_mut()).unwrap();
+    })
+}
+
+#[test]
+fn routes_by_key() {
+    let a = Rc::new(RefCell::new(Service::default()));
+    let b = Rc::new(RefCell::new(Service::default()));
+
+    let mut router = Router::new();
+    router.insert(1u32, service_proxy(&a));
+    router.insert(2u32, service_proxy(&b));
+
+    let ret = router.increment_by(1, 2).unwrap();
+    assert_eq!(*ret.borrow(), Some(2));
+    router.increment_by(2, 5).unwrap();
+    router.increment_by(1, 3).unwrap();
+    assert_eq!(a.borrow().counter, 5);
+    assert_eq!(b.borrow().counter, 5);
+
+    router.reset(2).unwrap();
+    assert_eq!(a.borrow().counter, 5);
+    assert_eq!(b.borrow().counter, 0);
+}
+
+#[test]
+fn unknown_key_is_an_error() {
+    let a = Rc::new(RefCell::new(Service::default()));
+
+    let mut router = Router::new();
+    router.insert(1u32, service_proxy(&a));
+
+    assert!(matches!(router.increment_by(3, 2), Err(UnknownRoute(3))));
+    assert_eq!(router.reset(4), Err(UnknownRoute(4)));
+
+    router.remove(&1);
+    assert!(router.increment_by(1, 2).is_err());
+    assert_eq!(a.borrow().counter, 0);
+}
+
+#[derive(Default)]
+struct Bank<K> {
+    accounts: Vec<(K, i64)>,
+}
+
+// The service's own `K` must not collide with the router trait's key parameter
+#[ctrlgen::ctrlgen(enum BankMsg, returnval = LocalRetval, router trait BankRouter)]
+impl<K: Clone + PartialEq> Bank<K> {
+    pub fn deposit(&mut self, account: K, amount: i64) -> i64 {
+        if let Some((_, balance)) = self.accounts.iter_mut().find(|(a, _)| *a == account) {
+            *balance += amount;
+            return *balance;
+        }
+        self.accounts.push((account, amount));
+        amount
+    }
+}
+
+#[test]
+fn generic_service_router() {
+    let bank = Rc::new(RefCell::new(Bank::<&str>::default()));
+    let proxy = {
+        let bank = bank.clone();
+        FnProxy::new(move |msg: BankMsg<&str>| {
+            msg.call_mut(&mut *bank.borrow_mut()).unwrap();
+        })
+    };
+
+    let mut router = Router::new();
+    router.insert('a', proxy);
+    assert_eq!(*router.deposit('a', "alice", 5).unwrap().borrow(), Some(5));
+    assert_eq!(*router.deposit('a', "alice", 3).unwrap().borrow(), Some(8));
+    assert_eq!(router.deposit('b', "bob", 1).err(), Some(UnknownRoute('b')));
+    assert_eq!(bank.borrow().accounts, [("alice", 8)]);
+}
@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::ops::Add;
+
+use ctrlgen::support::FnProxy;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Span<T> {
+    start: T,
+    end: T,
+}
+
+impl<T: Add<Output = T>> Add for Span<T> {
+    type Output = Span<T>;
+    fn add(self, other: Self) -> Span<T> {
+        Span {
+            start: self.start + other.start,
+            end: self.end + other.end,
+        }
+    }
+}
+
+#[ctrlgen::ctrlgen(
+    enum SpanMsg,
+    trait SpanProxy,
+    returnval = LocalRetval,
+)]
+impl<T: Copy + PartialOrd + Add<Output = T> + 'static> Span<T> {
+    pub fn merge(&mut self, other: Self) {
+        if other.start < self.start {
+            self.start = other.start;
+        }
+        if other.end > self.end {
+            self.end = other.end;
+        }
+    }
+
+    pub fn snapshot(&mut self) -> Self {
+        *self
+    }
+
+    pub fn split(&mut self, at: T) -> Option<(Self, Self)> {
+        if at < self.start || at > self.end {
+            return None;
+        }
+        Some((
+            Span {
+                start: self.start,
+                end: at,
+            },
+            Span {
+                start: at,
+                end: self.end,
+            },
+        ))
+    }
+
+    pub fn offset(&mut self, by: Self) -> <Self as Add>::Output {
+        *self + by
+    }
+
+    pub fn replace_with(&mut self, spans: Vec<Self>) {
+        if let Some(last) = spans.last() {
+            *self = *last;
+        }
+    }
+}
+
+#[test]
+fn self_in_arguments_and_return_types() {
+    let mut span = Span { start: 2, end: 4 };
+    SpanMsg::Merge {
+        other: Span { start: 1, end: 3 },
+    }
+    .call_mut(&mut span)
+    .unwrap();
+    assert_eq!(span, Span { start: 1, end: 4 });
+
+    let (tx, rx) = <LocalRetval as ctrlgen::Returnval>::create::<Span<i32>>();
+    SpanMsg::Snapshot { ret: tx }.call_mut(&mut span).unwrap();
+    assert_eq!(*rx.borrow(), Some(Span { start: 1, end: 4 }));
+}
+
+#[test]
+fn proxy_signatures_use_the_service_type() {
+    let span = RefCell::new(Span {
+        start: 0.0,
+        end: 1.0,
+    });
+    let proxy = FnProxy::new(|msg: SpanMsg<f32>| msg.call_mut(&mut span.borrow_mut()).unwrap());
+
+    let parts = proxy.split(0.5);
+    assert_eq!(
+        *parts.borrow(),
+        Some(Some((
+            Span {
+                start: 0.0,
+                end: 0.5
+            },
+            Span {
+                start: 0.5,
+                end: 1.0
+            }
+        )))
+    );
+
+    let moved = proxy.offset(Span {
+        start: 1.0,
+        end: 1.0,
+    });
+    assert_eq!(
+        *moved.borrow(),
+        Some(Span {
+            start: 1.0,
+            end: 2.0
+        })
+    );
+
+    proxy.replace_with(vec![Span {
+        start: 3.0,
+        end: 4.0,
+    }]);
+    proxy.merge(Span {
+        start: 2.0,
+        end: 3.0,
+    });
+    let snapshot = proxy.snapshot();
+    assert_eq!(
+        *snapshot.borrow(),
+        Some(Span {
+            start: 2.0,
+            end: 4.0
+        })
+    );
+}
+
+#[derive(Default)]
+struct Frame {
+    samples: [f32; 4],
+}
+
+#[ctrlgen::ctrlgen(enum FrameMsg)]
+impl Frame {
+    const LEN: usize = 4;
+
+    pub fn fill(&mut self, samples: [f32; Self::LEN]) {
+        self.samples = samples;
+    }
+}
+
+#[test]
+fn self_associated_items() {
+    let mut frame = Frame::default();
+    FrameMsg::Fill {
+        samples: [1.0; Frame::LEN],
+    }
+    .call_mut(&mut frame)
+    .unwrap();
+    assert_eq!(frame.samples, [1.0; 4]);
+}
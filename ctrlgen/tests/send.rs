@@ -0,0 +1,97 @@
+#![cfg(feature = "tokio")]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use ctrlgen::support::tokio::TokioRetval;
+use ctrlgen::CallMutAsyncSend;
+use ctrlgen::Returnval;
+use tokio::sync::mpsc;
+
+#[derive(Default)]
+struct Counter {
+    count: u32,
+}
+
+#[ctrlgen::ctrlgen(enum CounterMsg, returnval = TokioRetval, send)]
+impl Counter {
+    async fn add(&mut self, amount: u32) -> u32 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        self.count += amount;
+        self.count
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+struct Log<T> {
+    entries: Vec<T>,
+}
+
+#[ctrlgen::ctrlgen(enum LogMsg, returnval = TokioRetval, send)]
+impl<T: Clone + Send + 'static> Log<T> {
+    async fn push(&mut self, entry: T) -> usize {
+        tokio::task::yield_now().await;
+        self.entries.push(entry);
+        self.entries.len()
+    }
+
+    fn last(&self) -> Option<T> {
+        self.entries.last().cloned()
+    }
+}
+
+/// A service loop that works for any service, and can be spawned on a multi-threaded runtime
+async fn serve<S, Msg>(mut service: S, mut rx: mpsc::UnboundedReceiver<Msg>) -> S
+where
+    S: Send + 'static,
+    Msg: CallMutAsyncSend<S> + 'static,
+    Msg::Error: Debug,
+    for<'c> Msg::Context<'c>: ctrlgen::IsUnit,
+{
+    while let Some(msg) = rx.recv().await {
+        msg.call_mut_async_send(&mut service).await.unwrap();
+    }
+    service
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_messages_are_send() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let service = tokio::spawn(serve(Counter::default(), rx));
+
+    let (ret, first) = TokioRetval::create();
+    tx.send(CounterMsg::Add { amount: 2, ret }).unwrap();
+    let (ret, second) = TokioRetval::create();
+    tx.send(CounterMsg::Add { amount: 3, ret }).unwrap();
+    assert_eq!(first.await, Some(2));
+    assert_eq!(second.await, Some(5));
+
+    tx.send(CounterMsg::Reset {}).unwrap();
+    drop(tx);
+    assert_eq!(service.await.unwrap().count, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn generic_services_are_send() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let service = tokio::spawn(serve(Log { entries: vec![] }, rx));
+
+    let (ret, len) = TokioRetval::create();
+    tx.send(LogMsg::Push {
+        entry: "spawned".to_owned(),
+        ret,
+    })
+    .unwrap();
+    assert_eq!(len.await, Some(1));
+
+    let (ret, last) = TokioRetval::create();
+    tx.send(LogMsg::Last { ret }).unwrap();
+    assert_eq!(last.await, Some(Some("spawned".to_owned())));
+
+    drop(tx);
+    assert_eq!(service.await.unwrap().entries, ["spawned"]);
+}
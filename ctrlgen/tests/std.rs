@@ -0,0 +1,78 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use ctrlgen::support::std::StdProxy;
+use ctrlgen::support::std::StdRetval;
+use ctrlgen::CallMut;
+
+#[derive(Default)]
+struct Service {
+    counter: i32,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    pub enum ServiceMsg,
+    trait ServiceProxy,
+    returnval = StdRetval,
+)]
+impl Service {
+    pub fn increment_by(&mut self, arg: i32) -> i32 {
+        self.counter += arg;
+        self.counter
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+}
+
+fn spawn_service(rx: mpsc::Receiver<ServiceMsg>) -> std::thread::JoinHandle<Service> {
+    std::thread::spawn(move || {
+        let mut service = Service::default();
+        for msg in rx {
+            msg.call_mut(&mut service).unwrap();
+        }
+        service
+    })
+}
+
+#[test]
+fn unbounded_proxy() {
+    let (tx, rx) = mpsc::channel();
+    let handle = spawn_service(rx);
+    let proxy = StdProxy::new(tx);
+
+    assert_eq!(proxy.increment_by(2).recv(), Ok(2));
+    proxy.reset();
+    let ret = proxy.increment_by(3);
+    assert_eq!(ret.recv_timeout(Duration::from_secs(5)), Ok(3));
+
+    drop(proxy);
+    assert_eq!(handle.join().unwrap().counter, 3);
+}
+
+#[test]
+fn bounded_proxy_from_other_threads() {
+    let (tx, rx) = mpsc::sync_channel(1);
+    let handle = spawn_service(rx);
+    let proxy = StdProxy::from(tx);
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let proxy = proxy.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    proxy.increment_by(1).recv().unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(proxy.increment_by(0).recv(), Ok(40));
+    drop(proxy);
+    handle.join().unwrap();
+}
@@ -0,0 +1,129 @@
+use std::pin::Pin;
+use std::sync::mpsc;
+
+use ctrlgen::support::std::StdProxy;
+use ctrlgen::support::std::StdRetval;
+use ctrlgen::support::LocalRetval;
+use ctrlgen::CallMut;
+use ctrlgen::Returnval;
+use ctrlgen::StreamReturnval;
+
+#[derive(Default)]
+struct Library {
+    samples: Vec<String>,
+}
+
+#[ctrlgen::ctrlgen(
+    #[derive(Debug)]
+    pub enum LibraryMsg,
+    trait LibraryProxy,
+    returnval = StdRetval,
+)]
+impl Library {
+    pub fn add(&mut self, name: String) {
+        self.samples.push(name);
+    }
+
+    pub fn scan(&mut self, prefix: String) -> impl Iterator<Item = String> + '_ {
+        self.samples
+            .iter()
+            .filter(move |s| s.starts_with(&prefix))
+            .cloned()
+    }
+
+    pub fn count(&mut self) -> usize {
+        self.samples.len()
+    }
+}
+
+#[test]
+fn stream_over_std_channels() {
+    let (tx, rx) = mpsc::channel::<LibraryMsg>();
+    let handle = std::thread::spawn(move || {
+        let mut library = Library::default();
+        for msg in rx {
+            msg.call_mut(&mut library).unwrap();
+        }
+    });
+    let proxy = StdProxy::new(tx);
+
+    proxy.add("kick.wav".into());
+    proxy.add("snare.wav".into());
+    proxy.add("kick_808.wav".into());
+
+    let found: Vec<_> = proxy.scan("kick".into()).into_iter().collect();
+    assert_eq!(found, ["kick.wav", "kick_808.wav"]);
+    assert_eq!(proxy.count().recv(), Ok(3));
+
+    drop(proxy);
+    handle.join().unwrap();
+}
+
+#[derive(Default)]
+struct Counter {
+    calls: u32,
+}
+
+#[ctrlgen::ctrlgen(
+    enum CounterMsg,
+    returnval = LocalRetval,
+    skip_cancelled,
+)]
+impl Counter {
+    pub fn count_to(&mut self, n: u32) -> ctrlgen::Stream<'_, u32> {
+        self.calls += 1;
+        ctrlgen::Stream::new(1..=n)
+    }
+}
+
+#[test]
+fn declared_stream_return() {
+    let mut counter = Counter::default();
+
+    let (tx, rx) = LocalRetval::create_stream();
+    CounterMsg::CountTo { n: 3, ret: tx }
+        .call_mut(&mut counter)
+        .unwrap();
+    assert_eq!(rx.take(), [1, 2, 3]);
+
+    // Nobody is waiting for the stream, so the method is skipped
+    let (tx, rx) = LocalRetval::create_stream();
+    drop(rx);
+    CounterMsg::CountTo { n: 3, ret: tx }
+        .call_mut(&mut counter)
+        .unwrap();
+    assert_eq!(counter.calls, 1);
+}
+
+/// Not a `ctrlgen::Stream`, so returned as a single value
+#[derive(Debug, PartialEq)]
+struct Stream<T>(Vec<T>);
+
+struct Mixer;
+
+#[ctrlgen::ctrlgen(enum MixerMsg, returnval = LocalRetval)]
+impl Mixer {
+    pub fn streams(&mut self) -> Stream<u32> {
+        Stream(vec![1, 2])
+    }
+
+    pub fn levels(&mut self) -> Pin<Box<dyn futures_lite::Stream<Item = f32>>> {
+        Box::pin(futures_lite::stream::iter([0.5, 1.0]))
+    }
+}
+
+#[test]
+fn other_stream_types_are_plain_values() {
+    let (tx, rx) = LocalRetval::create();
+    MixerMsg::Streams { ret: tx }.call_mut(&mut Mixer).unwrap();
+    assert_eq!(rx.take(), Some(Stream(vec![1, 2])));
+}
+
+#[test]
+fn boxed_async_streams_are_plain_values() {
+    let (tx, rx) = LocalRetval::create();
+    MixerMsg::Levels { ret: tx }.call_mut(&mut Mixer).unwrap();
+    let levels = rx.take().unwrap();
+    let levels: Vec<f32> = futures_lite::future::block_on(futures_lite::StreamExt::collect(levels));
+    assert_eq!(levels, [0.5, 1.0]);
+}
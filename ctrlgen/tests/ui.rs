@@ -0,0 +1,8 @@
+// The expected diagnostics are those of the toolchain in rust-toolchain.toml
+#![cfg(feature = "nightly")]
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,147 @@
+struct EnumAttrGroup;
+#[ctrlgen::ctrlgen(enum EnumAttrGroupMsg)]
+impl EnumAttrGroup {
+    #[ctrlgen_enum_attr = "doc"]
+    fn foo(&mut self) {}
+}
+
+struct ReturnAttrUnit;
+#[ctrlgen::ctrlgen(enum ReturnAttrUnitMsg, returnval = ctrlgen::support::LocalRetval)]
+impl ReturnAttrUnit {
+    #[ctrlgen_return_attr[doc = "return"]]
+    fn foo(&mut self) {}
+}
+
+struct ToOwnedMethod;
+#[ctrlgen::ctrlgen(enum ToOwnedMethodMsg)]
+impl ToOwnedMethod {
+    #[ctrlgen_to_owned]
+    fn foo(&mut self, x: &str) {}
+}
+
+struct ToOwnedArgs;
+#[ctrlgen::ctrlgen(enum ToOwnedArgsMsg)]
+impl ToOwnedArgs {
+    fn foo(&mut self, #[ctrlgen_to_owned(yes)] x: &str) {}
+}
+
+struct ToOwnedValue;
+#[ctrlgen::ctrlgen(enum ToOwnedValueMsg)]
+impl ToOwnedValue {
+    fn foo(&mut self, #[ctrlgen_to_owned] x: String) {}
+}
+
+struct ContextAttr;
+#[ctrlgen::ctrlgen(enum ContextAttrMsg, context(ctx: u32))]
+impl ContextAttr {
+    fn foo(&mut self, #[ctrlgen_enum_attr[doc = "context"]] ctx: u32) {}
+}
+
+struct IntoConcrete;
+#[ctrlgen::ctrlgen(enum IntoConcreteMsg)]
+impl IntoConcrete {
+    fn foo(&mut self, #[ctrlgen_into(String)] x: String) {}
+}
+
+struct ImplNotInferred;
+#[ctrlgen::ctrlgen(enum ImplNotInferredMsg)]
+impl ImplNotInferred {
+    fn foo(&mut self, x: impl core::fmt::Display) {}
+}
+
+struct CoalesceTwice;
+#[ctrlgen::ctrlgen(enum CoalesceTwiceMsg)]
+impl CoalesceTwice {
+    #[ctrlgen_coalesce]
+    #[ctrlgen_coalesce]
+    fn foo(&mut self) {}
+}
+
+struct CoalesceKey;
+#[ctrlgen::ctrlgen(enum CoalesceKeyMsg)]
+impl CoalesceKey {
+    #[ctrlgen_coalesce(track)]
+    fn foo(&mut self, value: f32) {}
+}
+
+struct PriorityTwice;
+#[ctrlgen::ctrlgen(enum PriorityTwiceMsg)]
+impl PriorityTwice {
+    #[ctrlgen_priority(high)]
+    #[ctrlgen_priority(low)]
+    fn foo(&mut self) {}
+}
+
+struct PriorityLane;
+#[ctrlgen::ctrlgen(enum PriorityLaneMsg)]
+impl PriorityLane {
+    #[ctrlgen_priority(urgent)]
+    fn foo(&mut self) {}
+}
+
+struct AckArgs;
+#[ctrlgen::ctrlgen(enum AckArgsMsg, returnval = ctrlgen::support::LocalRetval)]
+impl AckArgs {
+    #[ctrlgen_ack(yes)]
+    fn foo(&mut self) {}
+}
+
+struct AckReturn;
+#[ctrlgen::ctrlgen(enum AckReturnMsg, returnval = ctrlgen::support::LocalRetval)]
+impl AckReturn {
+    #[ctrlgen_ack]
+    fn foo(&mut self) -> i32 {
+        1
+    }
+}
+
+struct AckWithoutReturnval;
+#[ctrlgen::ctrlgen(enum AckWithoutReturnvalMsg)]
+impl AckWithoutReturnval {
+    #[ctrlgen_ack]
+    fn foo(&mut self) {}
+}
+
+struct CancelUnit;
+#[ctrlgen::ctrlgen(
+    enum CancelUnitMsg,
+    returnval = ctrlgen::support::LocalRetval,
+    context(cancel: ctrlgen::CancellationToken),
+)]
+impl CancelUnit {
+    fn foo(&mut self, cancel: ctrlgen::CancellationToken<'_>) {}
+}
+
+struct CancelEnumAttr;
+#[ctrlgen::ctrlgen(
+    enum CancelEnumAttrMsg,
+    returnval = ctrlgen::support::LocalRetval,
+    context(cancel: ctrlgen::CancellationToken),
+)]
+impl CancelEnumAttr {
+    fn foo(
+        &mut self,
+        #[ctrlgen_enum_attr[doc = "cancel"]] cancel: ctrlgen::CancellationToken<'_>,
+    ) -> i32 {
+        1
+    }
+}
+
+struct BatchHookTwice;
+#[ctrlgen::ctrlgen(enum BatchHookTwiceMsg)]
+impl BatchHookTwice {
+    #[ctrlgen_batch_hook]
+    fn flush(&mut self) {}
+
+    #[ctrlgen_batch_hook]
+    fn flush_again(&mut self) {}
+}
+
+struct BatchHookSignature;
+#[ctrlgen::ctrlgen(enum BatchHookSignatureMsg)]
+impl BatchHookSignature {
+    #[ctrlgen_batch_hook]
+    fn flush(&self) {}
+}
+
+fn main() {}
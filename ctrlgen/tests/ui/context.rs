@@ -0,0 +1,12 @@
+struct Buffer;
+
+struct Mismatch;
+
+#[ctrlgen::ctrlgen(enum MismatchMsg, context(buffer: &mut Buffer, gain: f32))]
+impl Mismatch {
+    fn process(&mut self, buffer: &Buffer, gain: u32) {
+        let _ = (buffer, gain);
+    }
+}
+
+fn main() {}
@@ -0,0 +1,32 @@
+#![allow(incomplete_features)]
+#![feature(specialization)]
+
+struct DefaultImpl;
+#[ctrlgen::ctrlgen(enum DefaultImplMsg)]
+default impl DefaultImpl {}
+
+struct UnsafeImpl;
+#[ctrlgen::ctrlgen(enum UnsafeImplMsg)]
+unsafe impl UnsafeImpl {}
+
+trait Trait {}
+struct TraitImpl;
+#[ctrlgen::ctrlgen(enum TraitImplMsg)]
+impl Trait for TraitImpl {}
+
+trait Assoc {
+    type Service;
+}
+#[ctrlgen::ctrlgen(enum QselfMsg)]
+impl <() as Assoc>::Service {}
+
+#[ctrlgen::ctrlgen(enum SliceMsg)]
+impl [u8] {}
+
+struct DefaultFn;
+#[ctrlgen::ctrlgen(enum DefaultFnMsg)]
+impl DefaultFn {
+    default fn foo(&mut self) {}
+}
+
+fn main() {}
@@ -0,0 +1,83 @@
+struct ConstFn;
+#[ctrlgen::ctrlgen(enum ConstFnMsg)]
+impl ConstFn {
+    const fn foo(&self) {}
+}
+
+struct UnsafeFn;
+#[ctrlgen::ctrlgen(enum UnsafeFnMsg)]
+impl UnsafeFn {
+    unsafe fn foo(&mut self) {}
+}
+
+struct AbiFn;
+#[ctrlgen::ctrlgen(enum AbiFnMsg)]
+impl AbiFn {
+    extern "C" fn foo(&mut self) {}
+}
+
+struct GenericFn;
+#[ctrlgen::ctrlgen(enum GenericFnMsg)]
+impl GenericFn {
+    fn foo<T>(&mut self, t: T) {}
+}
+
+struct VariadicFn;
+#[ctrlgen::ctrlgen(enum VariadicFnMsg)]
+impl VariadicFn {
+    fn foo(&mut self, ...) {}
+}
+
+struct ReturnWithoutReturnval;
+#[ctrlgen::ctrlgen(enum ReturnWithoutReturnvalMsg)]
+impl ReturnWithoutReturnval {
+    fn foo(&mut self) -> i32 {
+        1
+    }
+}
+
+struct NoSelf;
+#[ctrlgen::ctrlgen(enum NoSelfMsg)]
+impl NoSelf {
+    fn foo(x: i32) {}
+}
+
+struct ReceiverLifetime<'a>(&'a str);
+#[ctrlgen::ctrlgen(enum ReceiverLifetimeMsg)]
+impl<'a> ReceiverLifetime<'a> {
+    fn foo(&'a mut self) {}
+}
+
+struct RefArgument;
+#[ctrlgen::ctrlgen(enum RefArgumentMsg)]
+impl RefArgument {
+    fn foo(&mut self, ref x: i32) {}
+}
+
+struct PatternArgument;
+#[ctrlgen::ctrlgen(enum PatternArgumentMsg)]
+impl PatternArgument {
+    fn foo(&mut self, (a, b): (i32, i32)) {}
+}
+
+struct RetArgument;
+#[ctrlgen::ctrlgen(enum RetArgumentMsg, returnval = ctrlgen::support::LocalRetval)]
+impl RetArgument {
+    fn foo(&mut self, ret: i32) {}
+}
+
+struct DeadlineArgument;
+#[ctrlgen::ctrlgen(enum DeadlineArgumentMsg, deadline)]
+impl DeadlineArgument {
+    fn foo(&mut self, deadline: i32) {}
+}
+
+struct AsyncStream;
+#[ctrlgen::ctrlgen(enum AsyncStreamMsg, returnval = ctrlgen::support::LocalRetval)]
+impl AsyncStream {
+    fn foo(&mut self) -> impl futures_lite::Stream<Item = i32> {
+        futures_lite::stream::iter([1, 2, 3])
+    }
+}
+
+fn main() {}
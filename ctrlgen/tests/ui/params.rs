@@ -0,0 +1,51 @@
+struct Unknown;
+#[ctrlgen::ctrlgen(enum UnknownMsg, foo)]
+impl Unknown {}
+
+struct ReturnvalTwice;
+#[ctrlgen::ctrlgen(enum ReturnvalTwiceMsg, returnval = ctrlgen::support::LocalRetval, returnval = ctrlgen::support::LocalRetval)]
+impl ReturnvalTwice {}
+
+struct ContextTwice;
+#[ctrlgen::ctrlgen(enum ContextTwiceMsg, context(a: i32), context(b: i32))]
+impl ContextTwice {}
+
+struct ContextEmpty;
+#[ctrlgen::ctrlgen(enum ContextEmptyMsg, context())]
+impl ContextEmpty {}
+
+struct ContextValueTwice;
+#[ctrlgen::ctrlgen(enum ContextValueTwiceMsg, context(a: i32, a: u32))]
+impl ContextValueTwice {}
+
+struct CancellationTokenTwice;
+#[ctrlgen::ctrlgen(enum CancellationTokenTwiceMsg, context(a: ctrlgen::CancellationToken, b: ctrlgen::CancellationToken))]
+impl CancellationTokenTwice {}
+
+struct AckAll;
+#[ctrlgen::ctrlgen(enum AckAllMsg, ack_all)]
+impl AckAll {}
+
+struct BadProxyKind;
+#[ctrlgen::ctrlgen(enum BadProxyKindMsg, sync trait BadProxy)]
+impl BadProxyKind {}
+
+struct BadProxy;
+#[ctrlgen::ctrlgen(enum BadProxyMsg, proxy(struct BadProxy))]
+impl BadProxy {}
+
+struct RefEnumTwice;
+#[ctrlgen::ctrlgen(enum RefEnumTwiceMsg, ref enum RefEnumTwiceRef, ref enum RefEnumTwiceRef2)]
+impl RefEnumTwice {}
+
+struct RefProxyWithoutRefEnum;
+#[ctrlgen::ctrlgen(enum RefProxyWithoutRefEnumMsg, ref trait RefProxy)]
+impl RefProxyWithoutRefEnum {}
+
+struct ConcurrentWithoutAsync;
+#[ctrlgen::ctrlgen(enum ConcurrentWithoutAsyncMsg, concurrent)]
+impl ConcurrentWithoutAsync {
+    fn sync_method(&self) {}
+}
+
+fn main() {}
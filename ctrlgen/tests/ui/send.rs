@@ -0,0 +1,27 @@
+#![feature(impl_trait_in_assoc_type)]
+
+use std::rc::Rc;
+
+struct Shared {
+    value: Rc<u32>,
+}
+
+#[ctrlgen::ctrlgen(enum SharedMsg, send)]
+impl Shared {
+    async fn bump(&mut self) {
+        self.value = Rc::new(*self.value + 1);
+    }
+}
+
+struct Local;
+
+#[ctrlgen::ctrlgen(enum LocalMsg, send)]
+impl Local {
+    async fn hold(&mut self) {
+        let rc = Rc::new(());
+        std::future::ready(()).await;
+        drop(rc);
+    }
+}
+
+fn main() {}